@@ -1,44 +1,63 @@
 use crate::{
     config::Config,
-    database::Database,
-    models::{HistoricalPosition, PaginatedResponse},
+    database::{Database, MetadataLimits, MetadataWrite, SlotPrecondition, DEFAULT_METADATA_SLOT},
+    export::{self, ExportFormat},
+    leaderboard, metrics,
+    models::{
+        CandleInterval, HistoricalPosition, HistoryQuery, HistorySort, LeaderboardEntry,
+        LeaderboardMetric, LeaderboardWindow, NoteBatchRequest, NoteHistoryEntry, NoteStatus,
+        NullifierBatchRequest, NullifierState, NullifierStatus, OpenPositionView, PaddedNoteResult,
+        PaddedNoteSlot, PaginatedResponse, Position, PositionStatus, UnspentNote, UserMetadata,
+    },
+    note_digest,
+    rate_limit::{self, RateLimiter},
+    risk, stats,
+    status::{SyncPhase, SyncSnapshot, SyncStatus},
+    tls,
 };
 use anyhow::Result;
 use axum::{
-    extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Json,
-    routing::get,
+    body::Body,
+    extract::{DefaultBodyLimit, Extension, FromRequestParts, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware,
+    response::{Json, Response},
+    routing::{get, post},
     Router,
 };
-use ethers::{abi::Address, types::H256, utils::keccak256};
+use ethers::{
+    abi::Address,
+    types::{H256, U256},
+    utils::keccak256,
+};
 use serde::Deserialize;
 use serde_json::Value;
-use std::{str::FromStr, sync::Arc};
-use tower_http::cors::{Any, CorsLayer};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 // The shared state for our Axum handlers
 type AppState = State<Arc<Database>>;
 
-async fn check_auth(headers: &HeaderMap) -> Result<[u8; 32], StatusCode> {
+/// Recovers the address that signed `x-message`.
+async fn recover_signer(headers: &HeaderMap) -> Result<Address, StatusCode> {
     // println!("[AUTH] Starting authentication check");
 
     let sig_header = headers
         .get("x-signature")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| {
+        .ok_or(
             // println!("[AUTH] Error: x-signature header missing or invalid");
             StatusCode::UNAUTHORIZED
-        })?;
+        )?;
     // println!("[AUTH] Found x-signature header");
 
     let msg_header = headers
         .get("x-message")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| {
+        .ok_or(
             // println!("[AUTH] Error: x-message header missing or invalid");
             StatusCode::UNAUTHORIZED
-        })?;
+        )?;
     // println!("[AUTH] Found x-message header");
 
     // 1. Decode the signature from the header
@@ -62,22 +81,142 @@ async fn check_auth(headers: &HeaderMap) -> Result<[u8; 32], StatusCode> {
         StatusCode::UNAUTHORIZED
     })?;
     // println!("[AUTH] EOA address recovered: {:?}", recovered_addr);
+    Ok(recovered_addr)
+}
 
-    // 3. Derive the public key using the exact same logic as the smart contract and TS client.
-    // println!("[AUTH] Deriving public key");
-    let pub_key = keccak256(recovered_addr.as_bytes());
-    // println!("[AUTH] Public key derived: {:?}", hex::encode(pub_key));
+/// The address that signed a request's `x-message`. The rate limiter
+/// recovers it first and leaves it in the request extensions, so handlers
+/// do not verify the signature a second time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Signer(pub Address);
 
-    // println!("[AUTH] Authentication check successful");
-    Ok(pub_key)
+impl Signer {
+    pub(crate) async fn recover(headers: &HeaderMap) -> Result<Self, StatusCode> {
+        recover_signer(headers).await.map(Signer)
+    }
+
+    /// Derives the public key using the exact same logic as the smart contract and TS client.
+    pub(crate) fn owner_pub_key(&self) -> [u8; 32] {
+        keccak256(self.0.as_bytes())
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Signer {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, StatusCode> {
+        match parts.extensions.get::<Signer>() {
+            Some(signer) => Ok(*signer),
+            None => Signer::recover(&parts.headers).await,
+        }
+    }
+}
+
+/// Attaches mark-price metrics to each open position. Positions are returned
+/// without metrics until the indexer has recorded an oracle price.
+fn with_metrics(db: &Database, positions: Vec<Position>) -> Result<Vec<OpenPositionView>> {
+    let mark_price = db.get_mark_price()?;
+    let constants = db.get_protocol_constants()?;
+    positions
+        .into_iter()
+        .map(|position| {
+            let metrics = match (&mark_price, &constants) {
+                (Some(mark), Some(constants)) => {
+                    Some(risk::compute_metrics(&position, mark, constants)?)
+                }
+                _ => None,
+            };
+            Ok(OpenPositionView { position, metrics })
+        })
+        .collect()
 }
 
 #[derive(Deserialize)]
-pub struct PaginationParams {
+pub struct HistoryParams {
+    cursor: Option<String>, // next_cursor of the previous page
+    page_size: Option<usize>,
+    status: Option<PositionStatus>, // "Closed" | "Liquidated"
+    is_long: Option<bool>,
+    from: Option<u64>,
+    to: Option<u64>,
+    min_size: Option<String>,
+    sort: Option<String>,  // "closed_at" | "pnl" | "size"
+    order: Option<String>, // "asc" | "desc"
+}
+
+impl HistoryParams {
+    fn to_query(&self) -> Result<HistoryQuery, StatusCode> {
+        if self.status == Some(PositionStatus::Open) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let min_size = match &self.min_size {
+            Some(size) => Some(U256::from_dec_str(size).map_err(|_| StatusCode::BAD_REQUEST)?),
+            None => None,
+        };
+        let sort = match self.sort.as_deref() {
+            None | Some("closed_at") => HistorySort::ClosedAt,
+            Some("pnl") => HistorySort::Pnl,
+            Some("size") => HistorySort::Size,
+            Some(_) => return Err(StatusCode::BAD_REQUEST),
+        };
+        let descending = match self.order.as_deref() {
+            None | Some("desc") => true,
+            Some("asc") => false,
+            Some(_) => return Err(StatusCode::BAD_REQUEST),
+        };
+        let after = match &self.cursor {
+            Some(cursor) => Some(hex::decode(cursor).map_err(|_| StatusCode::BAD_REQUEST)?),
+            None => None,
+        };
+        Ok(HistoryQuery {
+            status: self.status.clone(),
+            is_long: self.is_long,
+            from: self.from,
+            to: self.to,
+            min_size,
+            sort,
+            descending,
+            after,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PriceRangeParams {
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>,
+    interval: Option<String>,
+}
+
+const MAX_PRICE_POINTS: usize = 1_000;
+
+#[derive(Deserialize)]
+pub struct LeaderboardParams {
+    window: Option<String>,
+    metric: Option<String>,
     cursor: Option<usize>,
     page_size: Option<usize>,
 }
 
+const MAX_LEADERBOARD_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    format: Option<String>,  // "csv" | "ndjson"
+    account: Option<String>, // "private" | "public"
+}
+
+#[derive(Deserialize)]
+pub struct NoteFeedParams {
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+    cursor: Option<String>, // "<block>:<log_index>" of the last entry already seen
+    limit: Option<usize>,
+}
+
+const MAX_NOTE_FEED_PAGE_SIZE: usize = 1_000;
+
 // GET /positions/{positionId}
 async fn get_position_by_id(
     State(db): AppState,
@@ -97,11 +236,12 @@ async fn get_position_by_id(
 // GET /positions/open
 async fn get_private_open_positions(
     State(db): AppState,
-    headers: HeaderMap,
+    signer: Signer,
 ) -> Result<Json<Value>, StatusCode> {
-    let owner_pub_key = check_auth(&headers).await?;
+    let owner_pub_key = signer.owner_pub_key();
     let positions = db
         .get_open_positions(&owner_pub_key)
+        .and_then(|positions| with_metrics(&db, positions))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(serde_json::json!({ "open_positions": positions })))
 }
@@ -109,15 +249,16 @@ async fn get_private_open_positions(
 // GET /positions/history
 async fn get_private_historical_positions(
     State(db): AppState,
-    headers: HeaderMap,
-    Query(pagination): Query<PaginationParams>,
+    signer: Signer,
+    Query(params): Query<HistoryParams>,
 ) -> Result<Json<PaginatedResponse<HistoricalPosition>>, StatusCode> {
     // println!("[API] Received request for GET /positions/history");
-    let owner_pub_key = check_auth(&headers).await?;
-    let page_size = pagination.page_size.unwrap_or(20);
-    println!("[API] Attempting to get historical positions for public key: {:?} with page size: {} and cursor: {:?}", hex::encode(owner_pub_key), page_size, pagination.cursor);
+    let owner_pub_key = signer.owner_pub_key();
+    let query = params.to_query()?;
+    let page_size = params.page_size.unwrap_or(20);
+    println!("[API] Attempting to get historical positions for public key: {:?} with page size: {} and cursor: {:?}", hex::encode(owner_pub_key), page_size, params.cursor);
     let positions = db
-        .get_historical_positions(&owner_pub_key, pagination.cursor, page_size)
+        .query_historical_positions(&owner_pub_key, &query, page_size)
         .map_err(|e| {
             println!(
                 "[API] Error getting historical positions from database: {}",
@@ -129,20 +270,87 @@ async fn get_private_historical_positions(
     Ok(Json(positions))
 }
 
-// GET /notes/unspent
-async fn get_unspent_notes(
+/// Number of receiver hashes in every batch request, real plus decoys.
+const NOTE_BATCH_SIZE: usize = 16;
+/// Note slots returned per receiver hash and page, padded when fewer exist.
+const NOTES_PER_HASH: usize = 8;
+
+fn word_hex(value: U256) -> String {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn padding_slot() -> PaddedNoteSlot {
+    let zero = word_hex(U256::zero());
+    PaddedNoteSlot {
+        present: 0,
+        note_id: zero.clone(),
+        note_nonce: zero.clone(),
+        value: zero,
+    }
+}
+
+fn note_slot(note: &UnspentNote) -> Result<PaddedNoteSlot> {
+    let note_id = H256::from_str(note.note_id.strip_prefix("0x").unwrap_or(&note.note_id))?;
+    Ok(PaddedNoteSlot {
+        present: 1,
+        note_id: format!("0x{}", hex::encode(note_id.as_bytes())),
+        note_nonce: word_hex(U256::from(note.note.note_nonce)),
+        value: word_hex(U256::from_dec_str(&note.note.value)?),
+    })
+}
+
+// POST /private/notes/unspent/batch
+// Looks up a fixed-size, decoy-padded set of receiver hashes and returns a
+// response whose size does not depend on which hashes had notes. Nothing
+// about the individual hashes or hits is logged.
+async fn get_unspent_notes_batch(
     State(db): AppState,
-    headers: HeaderMap,
+    Json(request): Json<NoteBatchRequest>,
 ) -> Result<Json<Value>, StatusCode> {
-    println!("[API] Received request for GET /notes/unspent");
-    // For privacy, the user provides the hash they can build from their secret.
+    if request.receiver_hashes.len() != NOTE_BATCH_SIZE {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let receiver_hashes = request
+        .receiver_hashes
+        .iter()
+        .map(|h| H256::from_str(h.strip_prefix("0x").unwrap_or(h)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let skip = request.page.saturating_mul(NOTES_PER_HASH);
+    let mut results = Vec::with_capacity(NOTE_BATCH_SIZE);
+    for receiver_hash in receiver_hashes {
+        let notes = db
+            .get_unspent_notes(receiver_hash.as_bytes())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let mut slots = notes
+            .iter()
+            .skip(skip)
+            .take(NOTES_PER_HASH)
+            .map(note_slot)
+            .collect::<Result<Vec<_>>>()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        slots.resize_with(NOTES_PER_HASH, padding_slot);
+        results.push(PaddedNoteResult {
+            receiver_hash: format!("0x{}", hex::encode(receiver_hash.as_bytes())),
+            has_more: u8::from(notes.len() > skip + NOTES_PER_HASH),
+            notes: slots,
+        });
+    }
+    Ok(Json(serde_json::json!({ "results": results })))
+}
+
+/// For privacy, the user provides the hash they can build from their secret.
+fn receiver_hash_from_headers(headers: &HeaderMap) -> Result<Vec<u8>, StatusCode> {
     let receiver_hash_header = headers
         .get("x-receiver-hash")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| {
+        .ok_or(
             // println!("[API] Error: x-receiver-hash header missing or invalid");
             StatusCode::BAD_REQUEST
-        })?;
+        )?;
     // println!("[API] Found x-receiver-hash header {}" , receiver_hash_header);
 
     let receiver_hash = hex::decode(
@@ -154,6 +362,16 @@ async fn get_unspent_notes(
         println!("[API] Error decoding receiver hash: {}", e);
         StatusCode::BAD_REQUEST
     })?;
+    Ok(receiver_hash)
+}
+
+// GET /notes/unspent
+async fn get_unspent_notes(
+    State(db): AppState,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    println!("[API] Received request for GET /notes/unspent");
+    let receiver_hash = receiver_hash_from_headers(&headers)?;
     // println!("[API] Attempting to get unspent notes for receiver hash: {:?}", hex::encode(&receiver_hash));
     let notes = db.get_unspent_notes(&receiver_hash).map_err(|e| {
         println!("[API] Error getting unspent notes from database: {}", e);
@@ -163,41 +381,300 @@ async fn get_unspent_notes(
     Ok(Json(serde_json::json!({ "unspent_notes": notes })))
 }
 
-async fn set_metadata(
+const MAX_NULLIFIER_BATCH_SIZE: usize = 256;
+
+// POST /private/nullifiers/spent
+// Batch replacement for calling `TokenPool.isNullifierSpent` per nullifier.
+// Like the note batch, nothing about the queried nullifiers is logged.
+async fn get_nullifier_status(
+    State(db): AppState,
+    Json(request): Json<NullifierBatchRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    if request.nullifiers.is_empty() || request.nullifiers.len() > MAX_NULLIFIER_BATCH_SIZE {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let nullifiers = request
+        .nullifiers
+        .iter()
+        .map(|n| H256::from_str(n.strip_prefix("0x").unwrap_or(n)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut results = Vec::with_capacity(nullifiers.len());
+    for nullifier in nullifiers {
+        let spent = db
+            .get_spent_nullifier(nullifier.as_bytes())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        results.push(NullifierStatus {
+            nullifier: format!("0x{}", hex::encode(nullifier.as_bytes())),
+            status: if spent.is_some() {
+                NullifierState::Spent
+            } else {
+                NullifierState::Unknown
+            },
+            spent: spent.is_some(),
+            spent_at_block: spent.map(|s| s.block_number),
+        });
+    }
+
+    let metadata = request.start_nonce.and_then(|start_nonce| {
+        results
+            .iter()
+            .rposition(|status| status.spent)
+            .map(|index| UserMetadata {
+                last_used_nullifier_nonce: start_nonce + index as u64,
+            })
+    });
+    let indexed_from_block = db
+        .get_nullifiers_indexed_from()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(serde_json::json!({
+        "results": results,
+        "metadata": metadata,
+        "indexed_from_block": indexed_from_block,
+    })))
+}
+
+fn sum_values<'a>(mut notes: impl Iterator<Item = &'a UnspentNote>) -> Result<U256> {
+    notes.try_fold(U256::zero(), |total, note| {
+        Ok(total + U256::from_dec_str(&note.note.value)?)
+    })
+}
+
+// GET /private/notes/summary
+// Unspent and claimed totals plus every note ever sent to the receiver hash.
+async fn get_note_summary(
     State(db): AppState,
     headers: HeaderMap,
-    body: axum::body::Bytes,
-) -> Result<StatusCode, StatusCode> {
-    // println!("[API] Received request for POST /metadata");
-    if body.len() > 4096 {
-        // println!("[API] Error: Payload size ({}) exceeds 4096 bytes", body.len());
+) -> Result<Json<Value>, StatusCode> {
+    let receiver_hash = receiver_hash_from_headers(&headers)?;
+    let unspent = db.get_unspent_notes(&receiver_hash).map_err(|e| {
+        println!("[API] Error getting unspent notes from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let spent = db.get_spent_notes(&receiver_hash).map_err(|e| {
+        println!("[API] Error getting spent notes from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let unspent_total =
+        sum_values(unspent.iter()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let claimed_total =
+        sum_values(spent.iter().map(|s| &s.note)).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut history: Vec<NoteHistoryEntry> = unspent
+        .iter()
+        .map(|note| NoteHistoryEntry {
+            note: note.clone(),
+            status: NoteStatus::Unspent,
+            claimed_block: None,
+            claimed_tx_hash: None,
+        })
+        .chain(spent.into_iter().map(|spent| NoteHistoryEntry {
+            note: spent.note,
+            status: NoteStatus::Claimed,
+            claimed_block: Some(spent.claimed_block),
+            claimed_tx_hash: Some(spent.claimed_tx_hash),
+        }))
+        .collect();
+    // Note nonces are assigned by the pool in creation order.
+    history.sort_by_key(|entry| entry.note.note.note_nonce);
+
+    let claimed_count = history.len() - unspent.len();
+    Ok(Json(serde_json::json!({
+        "unspent_total": unspent_total.to_string(),
+        "unspent_count": unspent.len(),
+        "claimed_total": claimed_total.to_string(),
+        "claimed_count": claimed_count,
+        "notes": history,
+    })))
+}
+
+const MAX_METADATA_BLOB_SIZE: usize = 4096;
+const METADATA_LIMITS: MetadataLimits = MetadataLimits {
+    max_slots: 16,
+    history_limit: 10,
+};
+const MAX_SLOT_NAME_LEN: usize = 64;
+
+fn validate_slot_name(slot: &str) -> Result<(), StatusCode> {
+    let valid = !slot.is_empty()
+        && slot.len() <= MAX_SLOT_NAME_LEN
+        && slot
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if valid {
+        Ok(())
+    } else {
+        Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Expected slot version from `If-Match`; `missing` applies without one.
+fn precondition(
+    headers: &HeaderMap,
+    missing: SlotPrecondition,
+) -> Result<SlotPrecondition, StatusCode> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(missing);
+    };
+    let value = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+    let value = value.strip_prefix("W/").unwrap_or(value);
+    value
+        .trim_matches('"')
+        .parse()
+        .map(SlotPrecondition::Version)
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+fn json_response(
+    status: StatusCode,
+    version: Option<u64>,
+    body: Value,
+) -> Result<Response, StatusCode> {
+    let mut builder = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(version) = version {
+        builder = builder.header(header::ETAG, etag(version));
+    }
+    builder
+        .body(Body::from(body.to_string()))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn read_slot(db: &Database, signer: Signer, slot: &str) -> Result<Response, StatusCode> {
+    validate_slot_name(slot)?;
+    let owner_pub_key = signer.owner_pub_key();
+    let blob = db.get_metadata_slot(&owner_pub_key, slot).map_err(|e| {
+        println!("[API] Error getting metadata from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let version = blob.as_ref().map(|b| b.version);
+    json_response(
+        StatusCode::OK,
+        version,
+        serde_json::json!({
+            "slot": slot,
+            "version": version,
+            "encrypted_metadata": blob.map(|b| b.data),
+        }),
+    )
+}
+
+async fn write_slot(
+    db: &Database,
+    signer: Signer,
+    headers: &HeaderMap,
+    slot: &str,
+    body: &[u8],
+    without_if_match: SlotPrecondition,
+) -> Result<Response, StatusCode> {
+    validate_slot_name(slot)?;
+    if body.len() > MAX_METADATA_BLOB_SIZE {
         return Err(StatusCode::PAYLOAD_TOO_LARGE);
     }
-    let owner_pub_key = check_auth(&headers).await?;
-    // println!("[API] Attempting to set metadata for public key: {:?}", hex::encode(owner_pub_key));
-    db.user_metadata
-        .insert(owner_pub_key, body.to_vec())
+    let owner_pub_key = signer.owner_pub_key();
+    let precondition = precondition(headers, without_if_match)?;
+    let outcome = db
+        .write_metadata_slot(&owner_pub_key, slot, precondition, body, METADATA_LIMITS)
         .map_err(|e| {
             println!("[API] Error setting metadata in database: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-    // println!("[API] Successfully set metadata Body {:#?}", body);
-    Ok(StatusCode::OK)
+    match outcome {
+        MetadataWrite::Written(version) => json_response(
+            StatusCode::OK,
+            Some(version),
+            serde_json::json!({ "slot": slot, "version": version }),
+        ),
+        // The client must re-read, merge and retry with the current ETag.
+        MetadataWrite::Conflict(current) => json_response(
+            StatusCode::CONFLICT,
+            current,
+            serde_json::json!({ "slot": slot, "current_version": current }),
+        ),
+        MetadataWrite::TooManySlots => Err(StatusCode::UNPROCESSABLE_ENTITY),
+    }
 }
 
-// GET /metadata
-async fn get_metadata(State(db): AppState, headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
-    // println!("[API] Received request for GET /metadata");
-    let owner_pub_key = check_auth(&headers).await?;
-    // println!("[API] Attempting to get metadata for public key: {:?}", hex::encode(owner_pub_key));
-    let metadata = db.user_metadata.get(&owner_pub_key).map_err(|e| {
-        println!("[API] Error getting metadata from database: {}", e);
+// POST /private/metadata
+// Writes the `default` slot. With If-Match it is conditional like the named
+// slots; without, it overwrites, as before slots existed.
+async fn set_metadata(
+    State(db): AppState,
+    signer: Signer,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, StatusCode> {
+    write_slot(&db, signer, &headers, DEFAULT_METADATA_SLOT, &body, SlotPrecondition::Any).await
+}
+
+// GET /private/metadata
+async fn get_metadata(State(db): AppState, signer: Signer) -> Result<Response, StatusCode> {
+    read_slot(&db, signer, DEFAULT_METADATA_SLOT).await
+}
+
+// GET /private/metadata/slots
+async fn list_metadata_slots(
+    State(db): AppState,
+    signer: Signer,
+) -> Result<Json<Value>, StatusCode> {
+    let owner_pub_key = signer.owner_pub_key();
+    let slots = db.list_metadata_slots(&owner_pub_key).map_err(|e| {
+        println!("[API] Error listing metadata slots: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
-    // println!("[API] Successfully retrieved metadata");
-    Ok(Json(
-        serde_json::json!({ "encrypted_metadata": metadata.map(|m| hex::encode(m)) }),
-    ))
+    let slots: Vec<Value> = slots
+        .into_iter()
+        .map(|(slot, blob)| {
+            serde_json::json!({
+                "slot": slot,
+                "version": blob.version,
+                "updated_at": blob.updated_at,
+            })
+        })
+        .collect();
+    Ok(Json(serde_json::json!({ "slots": slots })))
+}
+
+// GET /private/metadata/slots/{slot}
+async fn get_metadata_slot(
+    State(db): AppState,
+    Path(slot): Path<String>,
+    signer: Signer,
+) -> Result<Response, StatusCode> {
+    read_slot(&db, signer, &slot).await
+}
+
+// PUT /private/metadata/slots/{slot}
+async fn put_metadata_slot(
+    State(db): AppState,
+    Path(slot): Path<String>,
+    signer: Signer,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, StatusCode> {
+    write_slot(&db, signer, &headers, &slot, &body, SlotPrecondition::Missing).await
+}
+
+// GET /private/metadata/slots/{slot}/history
+async fn get_metadata_slot_history(
+    State(db): AppState,
+    Path(slot): Path<String>,
+    signer: Signer,
+) -> Result<Json<Value>, StatusCode> {
+    validate_slot_name(&slot)?;
+    let owner_pub_key = signer.owner_pub_key();
+    let history = db.get_metadata_history(&owner_pub_key, &slot).map_err(|e| {
+        println!("[API] Error getting metadata history: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({ "slot": slot, "versions": history })))
 }
 
 async fn get_open_positions_for_address(
@@ -212,6 +689,7 @@ async fn get_open_positions_for_address(
 
     let positions = db
         .get_open_positions(&owner_id)
+        .and_then(|positions| with_metrics(&db, positions))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(serde_json::json!({ "open_positions": positions })))
 }
@@ -220,31 +698,427 @@ async fn get_open_positions_for_address(
 async fn get_historical_positions_for_address(
     State(db): AppState,
     Path(address_str): Path<String>,
-    Query(pagination): Query<PaginationParams>,
+    Query(params): Query<HistoryParams>,
 ) -> Result<Json<PaginatedResponse<HistoricalPosition>>, StatusCode> {
     let address: Address = address_str.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let query = params.to_query()?;
 
     let mut owner_id = [0u8; 32];
     owner_id[12..].copy_from_slice(address.as_bytes());
 
-    let page_size = pagination.page_size.unwrap_or(20);
+    let page_size = params.page_size.unwrap_or(20);
     let positions = db
-        .get_historical_positions(&owner_id, pagination.cursor, page_size)
+        .query_historical_positions(&owner_id, &query, page_size)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(positions))
 }
 
+// GET /prices/latest
+async fn get_latest_price(State(db): AppState) -> Result<Json<Value>, StatusCode> {
+    let mark_price = db
+        .get_mark_price()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match mark_price {
+        Some(mark_price) => Ok(Json(serde_json::json!({ "price": mark_price }))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// GET /prices/history?from&to
+async fn get_price_history(
+    State(db): AppState,
+    Query(params): Query<PriceRangeParams>,
+) -> Result<Json<Value>, StatusCode> {
+    let from = params.from.unwrap_or(0);
+    let to = params.to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params.limit.unwrap_or(MAX_PRICE_POINTS).min(MAX_PRICE_POINTS);
+    let prices = db.get_price_history(from, to, limit).map_err(|e| {
+        println!("[API] Error getting price history from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({ "prices": prices })))
+}
+
+// GET /prices/candles?interval=1m|5m|1h|1d&from&to
+async fn get_price_candles(
+    State(db): AppState,
+    Query(params): Query<PriceRangeParams>,
+) -> Result<Json<Value>, StatusCode> {
+    let interval = params
+        .interval
+        .as_deref()
+        .and_then(CandleInterval::parse)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let from = params.from.unwrap_or(0);
+    let to = params.to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params.limit.unwrap_or(MAX_PRICE_POINTS).min(MAX_PRICE_POINTS);
+    let candles = db.get_candles(interval, from, to, limit).map_err(|e| {
+        println!("[API] Error getting candles from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(
+        serde_json::json!({ "interval": interval.label(), "candles": candles }),
+    ))
+}
+
+fn parse_feed_cursor(cursor: &str) -> Option<(u64, u64)> {
+    let (block, log_index) = cursor.split_once(':')?;
+    Some((block.parse().ok()?, log_index.parse().ok()?))
+}
+
+// GET /notes/feed?from_block&to_block&cursor&limit
+// Every NoteCreated / NoteClaimed in block order, for local scanning.
+async fn get_note_feed(
+    State(db): AppState,
+    Query(params): Query<NoteFeedParams>,
+) -> Result<Json<Value>, StatusCode> {
+    let after = match params.cursor.as_deref() {
+        Some(cursor) => Some(parse_feed_cursor(cursor).ok_or(StatusCode::BAD_REQUEST)?),
+        None => match params.from_block {
+            Some(0) | None => None,
+            Some(from_block) => Some((from_block - 1, u64::MAX)),
+        },
+    };
+    let to_block = params.to_block.unwrap_or(u64::MAX);
+    let limit = params
+        .limit
+        .unwrap_or(MAX_NOTE_FEED_PAGE_SIZE)
+        .min(MAX_NOTE_FEED_PAGE_SIZE);
+    let entries = db.get_note_feed(after, to_block, limit).map_err(|e| {
+        println!("[API] Error getting note feed from database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let next_cursor = match entries.last() {
+        Some(last) if entries.len() == limit => {
+            Some(format!("{}:{}", last.block_number, last.log_index))
+        }
+        _ => None,
+    };
+    let head = db
+        .note_feed_head()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(serde_json::json!({
+        "entries": entries,
+        "next_cursor": next_cursor,
+        "head_block": head.map(|(block, _)| block),
+    })))
+}
+
+// GET /notes/filter
+// Bloom filter of receiver hashes with unspent notes, see `note_digest`.
+async fn get_note_filter(State(db): AppState) -> Result<Json<Value>, StatusCode> {
+    let filter = note_digest::receiver_filter(&db).map_err(|e| {
+        println!("[API] Error reading receiver filter: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({
+        "as_of_block": filter.as_of.map(|(block, _)| block),
+        "num_items": filter.num_items,
+        "num_bits": filter.num_bits,
+        "num_hashes": filter.num_hashes,
+        "bits": filter.bits,
+    })))
+}
+
+/// `part` as a share of `part + other`, in basis points.
+fn share_bps(part: U256, other: U256) -> String {
+    let total = part + other;
+    if total.is_zero() {
+        return "0".to_string();
+    }
+    (part * U256::from(10_000) / total).to_string()
+}
+
+// GET /stats
+async fn get_stats(State(db): AppState) -> Result<Json<Value>, StatusCode> {
+    let totals = db
+        .get_protocol_totals()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let volume_24h = db
+        .get_volume_since(stats::current_hour().saturating_sub(23))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({
+        "open_interest": {
+            "long": totals.long_open_interest.to_string(),
+            "short": totals.short_open_interest.to_string(),
+        },
+        "open_positions": totals.open_position_count,
+        "open_margin": totals.open_margin.to_string(),
+        "volume": {
+            "daily": volume_24h.to_string(),
+            "cumulative": totals.cumulative_volume.to_string(),
+        },
+        "taker_fees": totals.taker_fees.to_string(),
+        "liquidations": {
+            "count": totals.liquidation_count,
+            "volume": totals.liquidation_volume.to_string(),
+            "fees": totals.liquidation_fees.to_string(),
+        },
+        "margin": {
+            "added": totals.margin_added.to_string(),
+            "removed": totals.margin_removed.to_string(),
+        },
+        "activity": {
+            "private_positions_opened": totals.private_positions_opened,
+            "public_positions_opened": totals.public_positions_opened,
+            "private_volume": totals.private_volume.to_string(),
+            "public_volume": totals.public_volume.to_string(),
+            "private_position_share_bps": share_bps(
+                U256::from(totals.private_positions_opened),
+                U256::from(totals.public_positions_opened),
+            ),
+            "private_volume_share_bps": share_bps(totals.private_volume, totals.public_volume),
+        },
+    })))
+}
+
+// GET /leaderboard?window=24h|7d|all&metric=pnl|roi|win_rate|volume
+async fn get_leaderboard(
+    State(db): AppState,
+    Query(params): Query<LeaderboardParams>,
+) -> Result<Json<PaginatedResponse<LeaderboardEntry>>, StatusCode> {
+    let window = match params.window.as_deref() {
+        Some(label) => LeaderboardWindow::parse(label).ok_or(StatusCode::BAD_REQUEST)?,
+        None => LeaderboardWindow::AllTime,
+    };
+    let metric = match params.metric.as_deref() {
+        Some(label) => LeaderboardMetric::parse(label).ok_or(StatusCode::BAD_REQUEST)?,
+        None => LeaderboardMetric::Pnl,
+    };
+    let start = params.cursor.unwrap_or(0);
+    let page_size = params
+        .page_size
+        .unwrap_or(20)
+        .min(MAX_LEADERBOARD_PAGE_SIZE);
+
+    // Read-only: the indexer ages trades out of the rolling windows.
+    leaderboard::get_leaderboard(&db, window, metric, start, page_size + 1)
+        .map(|mut items| {
+            let has_more = items.len() > page_size;
+            items.truncate(page_size);
+            Json(PaginatedResponse {
+                next_cursor: has_more.then(|| (start + page_size).to_string()),
+                items,
+                has_more,
+            })
+        })
+        .map_err(|e| {
+            println!("[API] Error getting leaderboard from database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+// POST /private/leaderboard/opt-in
+async fn leaderboard_opt_in(
+    State(db): AppState,
+    signer: Signer,
+) -> Result<StatusCode, StatusCode> {
+    let owner_pub_key = signer.owner_pub_key();
+    leaderboard::set_opt_in(&db, &owner_pub_key, true)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+// DELETE /private/leaderboard/opt-in
+async fn leaderboard_opt_out(
+    State(db): AppState,
+    signer: Signer,
+) -> Result<StatusCode, StatusCode> {
+    let owner_pub_key = signer.owner_pub_key();
+    leaderboard::set_opt_in(&db, &owner_pub_key, false)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+// GET /private/export?format=csv|ndjson&account=private|public
+// Streams the signer's full account statement. `account=public` exports the
+// ClearingHouse activity of the signing EOA instead of its dark pool identity.
+async fn export_activity(
+    State(db): AppState,
+    signer: Signer,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, StatusCode> {
+    let format = match params.format.as_deref() {
+        Some(label) => ExportFormat::parse(label).ok_or(StatusCode::BAD_REQUEST)?,
+        None => ExportFormat::Csv,
+    };
+    let owner = match params.account.as_deref() {
+        None | Some("private") => signer.owner_pub_key().to_vec(),
+        Some("public") => {
+            let mut owner_id = [0u8; 32];
+            owner_id[12..].copy_from_slice(signer.0.as_bytes());
+            owner_id.to_vec()
+        }
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let header_row = match format {
+        ExportFormat::Csv => Some(export::CSV_HEADER.to_string()),
+        ExportFormat::Ndjson => None,
+    };
+    let rows = db.iter_activity(&owner).map(move |record| {
+        record
+            .map(|record| match format {
+                ExportFormat::Csv => export::csv_row(&record),
+                ExportFormat::Ndjson => export::ndjson_row(&record),
+            })
+            .map_err(|e| {
+                println!("[API] Error reading account activity: {}", e);
+                std::io::Error::other(e.to_string())
+            })
+    });
+    let body = Body::from_stream(futures::stream::iter(
+        header_row.into_iter().map(Ok).chain(rows),
+    ));
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"statement.{}\"", format.extension()),
+        )
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Reasons the replica should not receive traffic, empty when ready.
+fn readiness_failures(config: &Config, sync: &SyncSnapshot) -> Vec<String> {
+    let mut failures = Vec::new();
+    if sync.phase != SyncPhase::Realtime {
+        failures.push("backfill has not finished".to_string());
+    }
+    if sync.has_stream_errors() {
+        failures.push("an event stream is failing".to_string());
+    }
+    match (sync.lag_blocks, sync.lag_seconds) {
+        (Some(blocks), _) if config.ready_max_lag_blocks > 0 && blocks > config.ready_max_lag_blocks => {
+            failures.push(format!("lagging {} blocks behind head", blocks))
+        }
+        (_, Some(seconds)) if seconds > config.ready_max_lag_secs => {
+            failures.push(format!("lagging {}s behind head", seconds))
+        }
+        (None, _) => failures.push("no block indexed yet".to_string()),
+        _ => {}
+    }
+    if let Some(age) = sync.head_age_seconds {
+        if config.ready_max_head_age_secs > 0 && age > config.ready_max_head_age_secs {
+            failures.push(format!("no new head for {}s", age));
+        }
+    }
+    failures
+}
+
+// GET /ready
+// 503 while the indexer is behind, so load balancers skip stale replicas.
+async fn ready(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(sync): Extension<Arc<SyncStatus>>,
+) -> (StatusCode, Json<Value>) {
+    let failures = readiness_failures(&config, &sync.snapshot());
+    if failures.is_empty() {
+        (StatusCode::OK, Json(serde_json::json!({ "status": "ready" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "not_ready", "reasons": failures })),
+        )
+    }
+}
+
+// GET /status
+async fn status(
+    State(db): AppState,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(sync): Extension<Arc<SyncStatus>>,
+) -> Result<Json<Value>, StatusCode> {
+    let snapshot = sync.snapshot();
+    let db_size_bytes = db.size_on_disk().map_err(|e| {
+        println!("[API] Error reading database size: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({
+        "ready": readiness_failures(&config, &snapshot).is_empty(),
+        "sync": snapshot,
+        "db_size_bytes": db_size_bytes,
+    })))
+}
+
+// GET /metrics
+async fn metrics_endpoint(
+    Extension(sync): Extension<Arc<SyncStatus>>,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&sync),
+    )
+}
+
 // health route
 async fn health() -> Result<Json<Value>, StatusCode> {
     Ok(Json(serde_json::json!({ "status": "ok" })))
 }
 
-pub async fn run_api_server(config: Arc<Config>, db: Arc<Database>) -> Result<()> {
+/// Any origin when no allowlist is configured (local development); browsers
+/// reject wildcards on credentialed requests, so an allowlist lists
+/// methods and headers explicitly.
+fn cors_layer(config: &Config) -> Result<CorsLayer> {
+    let cors = CorsLayer::new().expose_headers([header::ETAG, header::RETRY_AFTER]);
+    if config.cors_allowed_origins.is_empty() {
+        return Ok(cors
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any));
+    }
+    let origins = config
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(cors
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::IF_MATCH,
+            HeaderName::from_static("x-signature"),
+            HeaderName::from_static("x-message"),
+            HeaderName::from_static("x-receiver-hash"),
+        ])
+        .allow_credentials(config.cors_allow_credentials))
+}
+
+pub async fn run_api_server(
+    config: Arc<Config>,
+    db: Arc<Database>,
+    sync: Arc<SyncStatus>,
+) -> Result<()> {
     // println!("[API Server] Initializing API server...");
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    let cors = cors_layer(&config)?;
+    let limiter = Arc::new(RateLimiter::new(&config));
+    tokio::spawn({
+        let limiter = Arc::clone(&limiter);
+        async move {
+            let mut interval = tokio::time::interval(rate_limit::SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                limiter.sweep();
+            }
+        }
+    });
+    tokio::spawn(metrics::refresh_db_metrics_periodically(Arc::clone(&db)));
     let app = Router::new()
         .route("/positions/{position_id}", get(get_position_by_id))
         .route(
@@ -261,14 +1135,67 @@ pub async fn run_api_server(config: Arc<Config>, db: Arc<Database>) -> Result<()
             get(get_private_historical_positions),
         )
         .route("/private/notes/unspent", get(get_unspent_notes))
+        .route("/private/notes/summary", get(get_note_summary))
+        .route("/private/nullifiers/spent", post(get_nullifier_status))
+        .route(
+            "/private/notes/unspent/batch",
+            post(get_unspent_notes_batch),
+        )
         .route("/private/metadata", get(get_metadata).post(set_metadata))
+        .route("/private/metadata/slots", get(list_metadata_slots))
+        .route(
+            "/private/metadata/slots/{slot}",
+            get(get_metadata_slot).put(put_metadata_slot),
+        )
+        .route(
+            "/private/metadata/slots/{slot}/history",
+            get(get_metadata_slot_history),
+        )
+        .route("/notes/feed", get(get_note_feed))
+        .route("/notes/filter", get(get_note_filter))
+        .route("/prices/latest", get(get_latest_price))
+        .route("/prices/history", get(get_price_history))
+        .route("/prices/candles", get(get_price_candles))
+        .route("/private/export", get(export_activity))
+        .route("/stats", get(get_stats))
+        .route("/leaderboard", get(get_leaderboard))
+        .route(
+            "/private/leaderboard/opt-in",
+            post(leaderboard_opt_in).delete(leaderboard_opt_out),
+        )
         .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics_endpoint))
         .with_state(Arc::clone(&db))
+        .layer(Extension(Arc::clone(&config)))
+        .layer(Extension(sync))
+        .layer(DefaultBodyLimit::max(config.max_request_body_bytes))
+        .layer(middleware::from_fn_with_state(
+            limiter,
+            rate_limit::limit,
+        ))
+        .layer(middleware::from_fn(metrics::track_requests))
         .layer(cors);
 
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    if let Some(tls_config) = tls::load(&config).await? {
+        let address: SocketAddr = config.server_bind_address.parse()?;
+        tokio::spawn({
+            let tls_config = tls_config.clone();
+            let config = Arc::clone(&config);
+            async move { tls::watch_certificates(tls_config, &config).await }
+        });
+        println!("[API] Serving HTTPS on {}", address);
+        axum_server::bind_rustls(address, tls_config)
+            .serve(service)
+            .await?;
+        return Ok(());
+    }
+
     // println!("[API Server] Binding to address: {}", &config.server_bind_address);
     let listener = tokio::net::TcpListener::bind(&config.server_bind_address).await?;
     // println!("[API Server] Listening on http://{}", &config.server_bind_address);
-    axum::serve(listener, app).await?;
+    axum::serve(listener, service).await?;
     Ok(())
 }
@@ -8,12 +8,48 @@ pub struct Config {
     pub db_path: String,
     pub server_bind_address: String,
     pub token_address: String,
+    // Token-bucket budgets per client IP and per owner pubkey; 0 disables a budget.
+    pub rate_limit_read_per_minute: u32,
+    pub rate_limit_read_burst: u32,
+    pub rate_limit_write_per_minute: u32,
+    pub rate_limit_write_burst: u32,
+    // Proxies in front of the API that append to `X-Forwarded-For`; the
+    // client IP is read that many entries from the right. 0 ignores the header.
+    pub trusted_proxy_hops: usize,
+    pub max_request_body_bytes: usize,
+    // Frontend origins allowed by CORS; empty allows any origin.
+    pub cors_allowed_origins: Vec<String>,
+    // Send `Access-Control-Allow-Credentials` (requires an origin allowlist).
+    pub cors_allow_credentials: bool,
+    // PEM paths; when both are set the API is served over TLS.
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_reload_interval_secs: u64,
+    // `/ready` fails beyond these; 0 disables the block and head age checks.
+    // Realtime progress is only confirmed one checkpoint (15-30s) behind the
+    // head, so the block check is off by default: on fast chains any useful
+    // block count is below that built-in lag.
+    pub ready_max_lag_blocks: u64,
+    pub ready_max_lag_secs: u64,
+    pub ready_max_head_age_secs: u64,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, anyhow::Error>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, anyhow::Error> {
         dotenv::dotenv().ok();
-        Ok(Self {
+        let config = Self {
             rpc_url: env::var("RPC_URL")?,
             privacy_proxy_address: env::var("PRIVACY_PROXY_ADDRESS")?,
             token_pool_address: env::var("TOKEN_POOL_ADDRESS")?,
@@ -21,6 +57,40 @@ impl Config {
             server_bind_address: env::var("SERVER_BIND_ADDRESS")
                 .unwrap_or_else(|_| "0.0.0.0:3000".to_string()),
             token_address: env::var("TOKEN_ADDRESS").expect("Token address not set"),
-        })
+            rate_limit_read_per_minute: env_or("RATE_LIMIT_READ_PER_MINUTE", 300)?,
+            rate_limit_read_burst: env_or("RATE_LIMIT_READ_BURST", 60)?,
+            rate_limit_write_per_minute: env_or("RATE_LIMIT_WRITE_PER_MINUTE", 30)?,
+            rate_limit_write_burst: env_or("RATE_LIMIT_WRITE_BURST", 10)?,
+            // `TRUST_FORWARDED_FOR=true` predates the hop count and means one proxy.
+            trusted_proxy_hops: env_or(
+                "TRUSTED_PROXY_HOPS",
+                env_or("TRUST_FORWARDED_FOR", false)? as usize,
+            )?,
+            max_request_body_bytes: env_or("MAX_REQUEST_BODY_BYTES", 16 * 1024)?,
+            cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS")
+                .map(|origins| {
+                    origins
+                        .split(',')
+                        .map(|origin| origin.trim().to_string())
+                        .filter(|origin| !origin.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            cors_allow_credentials: env_or("CORS_ALLOW_CREDENTIALS", false)?,
+            tls_cert_path: env::var("TLS_CERT_PATH").ok(),
+            tls_key_path: env::var("TLS_KEY_PATH").ok(),
+            tls_reload_interval_secs: env_or("TLS_RELOAD_INTERVAL_SECS", 30)?,
+            ready_max_lag_blocks: env_or("READY_MAX_LAG_BLOCKS", 0)?,
+            ready_max_lag_secs: env_or("READY_MAX_LAG_SECS", 120)?,
+            ready_max_head_age_secs: env_or("READY_MAX_HEAD_AGE_SECS", 300)?,
+        };
+
+        if config.cors_allow_credentials && config.cors_allowed_origins.is_empty() {
+            anyhow::bail!("CORS_ALLOW_CREDENTIALS requires CORS_ALLOWED_ORIGINS");
+        }
+        if config.tls_cert_path.is_some() != config.tls_key_path.is_some() {
+            anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together");
+        }
+        Ok(config)
     }
 }
@@ -1,8 +1,44 @@
 use anyhow::Result;
-use sled::{Db, Tree};
-use std::sync::Arc;
+use ethers::types::{I256, U256};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError, TransactionResult},
+    Db, IVec, Transactional, Tree,
+};
+use std::{iter::Peekable, ops::Bound, sync::Arc};
 
-use crate::models::{HistoricalPosition, PaginatedResponse, Position, PositionStatus, UnspentNote};
+use crate::models::{
+    ActivityRecord, Candle, CandleInterval, HistoricalPosition, HistoryIndexEntry, HistoryQuery,
+    HistorySort, MarkPrice, MetadataBlob, NoteFeedEntry, PaginatedResponse, Position,
+    PositionStatus, ProtocolConstants, ProtocolTotals, SpentNote, SpentNullifier, UnspentNote,
+};
+use crate::stats;
+
+/// Slot used by the original single-blob `/private/metadata` endpoint.
+pub const DEFAULT_METADATA_SLOT: &str = "default";
+
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataLimits {
+    pub max_slots: usize,
+    pub history_limit: usize,
+}
+
+/// The slot state a metadata write is conditional on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlotPrecondition {
+    /// Overwrite whatever is there (the legacy single-blob endpoint).
+    Any,
+    /// Create only.
+    Missing,
+    Version(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetadataWrite {
+    Written(u64),
+    /// The slot's actual version, `None` if it does not exist.
+    Conflict(Option<u64>),
+    TooManySlots,
+}
 
 #[derive(Clone)]
 pub struct Database {
@@ -13,12 +49,241 @@ pub struct Database {
     pub historical_positions: Tree,
     // K: receiver_hash (bytes), V: Vec<UnspentNote> (json)
     pub unspent_notes: Tree,
+    // K: receiver_hash (bytes) ++ note_id (bytes), V: SpentNote (json)
+    pub spent_notes: Tree,
+    // K: nullifier (bytes32), V: SpentNullifier (json)
+    pub spent_nullifiers: Tree,
+    // Legacy single-blob metadata, migrated into `metadata_slots` on startup.
     // K: owner_pub_key (bytes), V: encrypted metadata (bytes)
     pub user_metadata: Tree,
+    // K: owner_pub_key (bytes) ++ slot name, V: MetadataBlob (json)
+    pub metadata_slots: Tree,
+    // K: owner_pub_key (bytes), V: number of slots (u64 BE)
+    pub metadata_slot_counts: Tree,
+    // K: owner_pub_key (bytes) ++ slot name ++ 0x00 ++ version (u64 BE), V: MetadataBlob (json)
+    pub metadata_history: Tree,
     // V2: Reverse lookup for efficiency
     // K: position_id (bytes), V: owner_pub_key (bytes)
     pub position_id_to_owner: Tree,
     pub positions_by_id: Tree,
+    // K: static key (e.g. "constants", "mark_price"), V: json
+    pub protocol_state: Tree,
+    // K: timestamp (u64 BE) ++ block number (u64 BE) ++ log index (u64 BE), V: MarkPrice (json)
+    pub price_history: Tree,
+    // K: interval label ++ bucket start (u64 BE), V: Candle (json)
+    pub price_candles: Tree,
+    // K: "totals", V: ProtocolTotals (json)
+    pub protocol_stats: Tree,
+    // K: unix hour (u64 BE), V: notional volume (U256 BE)
+    pub hourly_volume: Tree,
+    // K: window label ++ owner key, V: TraderStats (json)
+    pub leaderboard_stats: Tree,
+    // K: window ++ metric ++ sortable score ++ owner key, V: empty
+    pub leaderboard_rank: Tree,
+    // K: close timestamp (u64 BE) ++ position_id, V: realized trade (json)
+    pub leaderboard_trades: Tree,
+    // K: owner_pub_key (bytes), V: opt-in marker
+    pub leaderboard_opt_in: Tree,
+    // K: owner key ++ sort tag ++ status ++ direction ++ sort value ++ position_id,
+    // V: HistoryIndexEntry (json)
+    pub history_index: Tree,
+    // K: owner key ++ block number (u64 BE) ++ log index (u64 BE), V: ActivityRecord (json)
+    pub account_activity: Tree,
+    // K: block number (u64 BE) ++ log index (u64 BE), V: NoteFeedEntry (json)
+    pub note_feed: Tree,
+}
+
+const CONSTANTS_KEY: &str = "constants";
+const MARK_PRICE_KEY: &str = "mark_price";
+const TOTALS_KEY: &str = "totals";
+const NULLIFIERS_INDEXED_FROM_KEY: &str = "nullifiers_indexed_from";
+const HISTORY_INDEX_VERSION_KEY: &str = "history_index_version";
+/// Bumped whenever the `history_index` layout or sort values change, so the
+/// index is rebuilt from `historical_positions` on the next start.
+const HISTORY_INDEX_VERSION: u64 = 2;
+/// Index rows a history page may read before returning early with a cursor.
+const HISTORY_SCAN_BUDGET: usize = 1_000;
+
+type IndexIter = Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>>;
+
+fn history_prefix(
+    owner_pub_key: &[u8],
+    sort: HistorySort,
+    status: &PositionStatus,
+    is_long: bool,
+) -> Vec<u8> {
+    let mut key = owner_pub_key.to_vec();
+    key.push(match sort {
+        HistorySort::ClosedAt => b't',
+        HistorySort::Pnl => b'p',
+        HistorySort::Size => b's',
+    });
+    key.push(match status {
+        PositionStatus::Liquidated => b'l',
+        _ => b'c',
+    });
+    key.push(if is_long { b'L' } else { b'S' });
+    key
+}
+
+/// Realized PnL as the leaderboard counts it: a close nets out its fee and a
+/// liquidation loses the remaining margin.
+fn realized_pnl(position: &HistoricalPosition) -> Result<I256> {
+    match position.status {
+        PositionStatus::Liquidated => {
+            Ok(-I256::from_raw(U256::from_dec_str(&position.position.margin)?))
+        }
+        _ => {
+            let fee = match position.fee.as_str() {
+                "" => U256::zero(),
+                fee => U256::from_dec_str(fee)?,
+            };
+            Ok(stats::net_pnl(I256::from_dec_str(&position.final_pnl)?, fee))
+        }
+    }
+}
+
+/// Order-preserving encoding of an entry's value for `sort`.
+fn history_sort_value(sort: HistorySort, entry: &HistoryIndexEntry) -> Result<Vec<u8>> {
+    let mut bytes = [0u8; 32];
+    match sort {
+        HistorySort::ClosedAt => return Ok(entry.closed_at.to_be_bytes().to_vec()),
+        HistorySort::Pnl => {
+            I256::from_dec_str(&entry.realized_pnl)?
+                .into_raw()
+                .to_big_endian(&mut bytes);
+            bytes[0] ^= 0x80; // sign-flip so negative values sort first
+        }
+        HistorySort::Size => U256::from_dec_str(&entry.size)?.to_big_endian(&mut bytes),
+    }
+    Ok(bytes.to_vec())
+}
+
+/// Sort value ++ position id: the index key after its prefix, and the cursor.
+fn history_sort_key(sort: HistorySort, entry: &HistoryIndexEntry) -> Result<Vec<u8>> {
+    let mut key = history_sort_value(sort, entry)?;
+    key.extend_from_slice(&hex::decode(
+        entry.position_id.strip_prefix("0x").unwrap_or(&entry.position_id),
+    )?);
+    Ok(key)
+}
+
+/// Pops the head that sorts first across streams that are each in order.
+fn pop_merged(
+    streams: &mut [(usize, Peekable<IndexIter>)],
+    descending: bool,
+) -> Result<Option<(Vec<u8>, sled::IVec)>> {
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for (i, (prefix_len, stream)) in streams.iter_mut().enumerate() {
+        if let Some(Err(_)) = stream.peek() {
+            stream.next().transpose()?;
+        }
+        if let Some(Ok((key, _))) = stream.peek() {
+            let sort_key = key[*prefix_len..].to_vec();
+            let better = match &best {
+                None => true,
+                Some((_, best_key)) if descending => sort_key > *best_key,
+                Some((_, best_key)) => sort_key < *best_key,
+            };
+            if better {
+                best = Some((i, sort_key));
+            }
+        }
+    }
+    let Some((index, sort_key)) = best else {
+        return Ok(None);
+    };
+    let (_, value) = streams[index].1.next().expect("peeked")?;
+    Ok(Some((sort_key, value)))
+}
+
+fn metadata_key(owner_pub_key: &[u8], slot: &str) -> Vec<u8> {
+    let mut key = owner_pub_key.to_vec();
+    key.extend_from_slice(slot.as_bytes());
+    key
+}
+
+fn metadata_history_prefix(owner_pub_key: &[u8], slot: &str) -> Vec<u8> {
+    let mut key = metadata_key(owner_pub_key, slot);
+    key.push(0);
+    key
+}
+
+fn read_slot_count(raw: Option<IVec>) -> Result<u64> {
+    match raw {
+        Some(raw) => Ok(u64::from_be_bytes(raw.as_ref().try_into()?)),
+        None => Ok(0),
+    }
+}
+
+fn note_feed_key(block_number: u64, log_index: u64) -> Vec<u8> {
+    let mut key = block_number.to_be_bytes().to_vec();
+    key.extend_from_slice(&log_index.to_be_bytes());
+    key
+}
+
+/// Oracle updates carry the block timestamp, so this orders them by chain position.
+fn price_history_key(timestamp: u64, block_number: u64, log_index: u64) -> Vec<u8> {
+    let mut key = timestamp.to_be_bytes().to_vec();
+    key.extend_from_slice(&block_number.to_be_bytes());
+    key.extend_from_slice(&log_index.to_be_bytes());
+    key
+}
+
+/// Aborts a sled transaction with an arbitrary error.
+fn abort(e: impl Into<anyhow::Error>) -> ConflictableTransactionError<anyhow::Error> {
+    ConflictableTransactionError::Abort(e.into())
+}
+
+fn transaction_result<T>(result: TransactionResult<T, anyhow::Error>) -> Result<T> {
+    result.map_err(|e| match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => e.into(),
+    })
+}
+
+/// Folds one oracle update into the candle of its bucket.
+fn fold_candle(
+    candle: Option<Candle>,
+    interval: CandleInterval,
+    update: &MarkPrice,
+    price: U256,
+) -> Result<Candle> {
+    let Some(mut candle) = candle else {
+        return Ok(Candle {
+            open_time: interval.bucket_start(update.timestamp),
+            open: update.price.clone(),
+            high: update.price.clone(),
+            low: update.price.clone(),
+            close: update.price.clone(),
+            first_update: update.timestamp,
+            last_update: update.timestamp,
+            num_updates: 1,
+        });
+    };
+    if price > U256::from_dec_str(&candle.high)? {
+        candle.high = update.price.clone();
+    }
+    if price < U256::from_dec_str(&candle.low)? {
+        candle.low = update.price.clone();
+    }
+    if update.timestamp < candle.first_update {
+        candle.open = update.price.clone();
+        candle.first_update = update.timestamp;
+    }
+    if update.timestamp >= candle.last_update {
+        candle.close = update.price.clone();
+        candle.last_update = update.timestamp;
+    }
+    candle.num_updates += 1;
+    Ok(candle)
+}
+
+fn candle_key(interval: CandleInterval, bucket_start: u64) -> Vec<u8> {
+    let mut key = interval.label().as_bytes().to_vec();
+    key.push(b':');
+    key.extend_from_slice(&bucket_start.to_be_bytes());
+    key
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -30,24 +295,51 @@ pub enum PositionData {
 
 impl Database {
     pub fn new(path: &str) -> Result<Self> {
-        let _db = Arc::new(sled::open(path)?);
+        Self::from_db(sled::open(path)?)
+    }
+
+    pub(crate) fn from_db(db: Db) -> Result<Self> {
+        let _db = Arc::new(db);
         Ok(Self {
             open_positions: _db.open_tree("open_positions")?,
             historical_positions: _db.open_tree("historical_positions")?,
             unspent_notes: _db.open_tree("unspent_notes")?,
+            spent_notes: _db.open_tree("spent_notes")?,
+            spent_nullifiers: _db.open_tree("spent_nullifiers")?,
             user_metadata: _db.open_tree("user_metadata")?,
+            metadata_slots: _db.open_tree("metadata_slots")?,
+            metadata_slot_counts: _db.open_tree("metadata_slot_counts")?,
+            metadata_history: _db.open_tree("metadata_history")?,
             position_id_to_owner: _db.open_tree("pos_id_to_owner")?,
             positions_by_id: _db.open_tree("positions_by_id")?, 
+            protocol_state: _db.open_tree("protocol_state")?,
+            price_history: _db.open_tree("price_history")?,
+            price_candles: _db.open_tree("price_candles")?,
+            protocol_stats: _db.open_tree("protocol_stats")?,
+            hourly_volume: _db.open_tree("hourly_volume")?,
+            leaderboard_stats: _db.open_tree("leaderboard_stats")?,
+            leaderboard_rank: _db.open_tree("leaderboard_rank")?,
+            leaderboard_trades: _db.open_tree("leaderboard_trades")?,
+            leaderboard_opt_in: _db.open_tree("leaderboard_opt_in")?,
+            history_index: _db.open_tree("history_index")?,
+            account_activity: _db.open_tree("account_activity")?,
+            note_feed: _db.open_tree("note_feed")?,
             _db,
         })
+        .and_then(|db| {
+            db.backfill_history_index()?;
+            db.migrate_legacy_metadata()?;
+            Ok(db)
+        })
     }
 
-    pub fn add_open_position(&self, owner_pub_key: &[u8], position: Position) -> Result<()> {
+    /// Returns `true` if the position was not already indexed.
+    pub fn add_open_position(&self, owner_pub_key: &[u8], position: Position) -> Result<bool> {
         let mut positions = self.get_open_positions(owner_pub_key)?;
-        if !positions
+        let is_new = !positions
             .iter()
-            .any(|p| p.position_id == position.position_id)
-        {
+            .any(|p| p.position_id == position.position_id);
+        if is_new {
             positions.push(position.clone());
         }
         self.open_positions
@@ -59,23 +351,26 @@ impl Database {
 
         println!("positions_by_id insert {}" , position.position_id);
         // println!("Inserted position Id for {:#?} owner {:#?}" , position.position_id, hex::encode(owner_pub_key));
-        Ok(())
+        Ok(is_new)
     }
 
+    /// Returns the owner key and the position that was moved, if it was open.
     pub fn move_to_historical(
         &self,
         position_id: &[u8],
         status: PositionStatus,
         final_pnl: String,
+        fee: String,
         owner_address: String, 
-    ) -> Result<()> {
+        closed_at: u64,
+    ) -> Result<Option<(Vec<u8>, Position)>> {
         // println!("Moving to historical records {:#?}" , format!("0x{}" , hex::encode(position_id)));
         let owner_pub_key = match self
             .position_id_to_owner
             .get(format!("0x{}", hex::encode(position_id)))?
         {
             Some(pk) => pk,
-            None => return Ok(()), // Position owner not found, maybe already processed
+            None => return Ok(None), // Position owner not found, maybe already processed
         };
 
         // println!("Owner of position {:#?}" , hex::encode(&owner_pub_key));
@@ -92,11 +387,14 @@ impl Database {
                 .insert(&owner_pub_key, serde_json::to_vec(&open_positions)?)?;
 
             let historical_pos = HistoricalPosition {
-                position: position_to_move,
+                position: position_to_move.clone(),
                 status,
                 final_pnl,
-                owner_address
+                fee,
+                owner_address,
+                closed_at,
             };
+            self.index_historical_position(&owner_pub_key, &historical_pos)?;
 
             let mut historical_positions =
                 self.get_historical_positions_internal(&owner_pub_key)?;
@@ -111,11 +409,86 @@ impl Database {
 
             // self.position_id_to_owner.remove()
             // println!("Removed position {:#?}" , position_id);
+            return Ok(Some((owner_pub_key.to_vec(), position_to_move)));
         }
 
+        Ok(None)
+    }
+
+    /// Applies a `MarginAdded` / `MarginRemoved` delta to an open position.
+    /// Returns `true` if an indexed open position was updated.
+    pub fn update_position_margin(
+        &self,
+        position_id: &[u8],
+        amount: U256,
+        is_addition: bool,
+    ) -> Result<bool> {
+        let position_key = format!("0x{}", hex::encode(position_id));
+        let owner_pub_key = match self.position_id_to_owner.get(&position_key)? {
+            Some(pk) => pk,
+            None => return Ok(false), // Not an indexed open position
+        };
+
+        let mut open_positions = self.get_open_positions(&owner_pub_key)?;
+        if let Some(position) = open_positions
+            .iter_mut()
+            .find(|p| p.position_id == position_key)
+        {
+            let margin = U256::from_dec_str(&position.margin)?;
+            let new_margin = if is_addition {
+                margin + amount
+            } else {
+                margin.saturating_sub(amount)
+            };
+            position.margin = new_margin.to_string();
+
+            let data = PositionData::Open(position.clone());
+            self.positions_by_id
+                .insert(position_key.as_bytes(), serde_json::to_vec(&data)?)?;
+            self.open_positions
+                .insert(&owner_pub_key, serde_json::to_vec(&open_positions)?)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn get_position_owner(&self, position_id: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .position_id_to_owner
+            .get(format!("0x{}", hex::encode(position_id)))?
+            .map(|owner| owner.to_vec()))
+    }
+
+    // --- Account Activity ---
+
+    /// Appends a ledger entry. Keyed by log position so replays are idempotent.
+    pub fn record_activity(
+        &self,
+        owner_pub_key: &[u8],
+        log_index: u64,
+        record: &ActivityRecord,
+    ) -> Result<()> {
+        let mut key = owner_pub_key.to_vec();
+        key.extend_from_slice(&record.block_number.to_be_bytes());
+        key.extend_from_slice(&log_index.to_be_bytes());
+        self.account_activity
+            .insert(key, serde_json::to_vec(record)?)?;
         Ok(())
     }
 
+    /// All ledger entries for an owner in chain order, read lazily.
+    pub fn iter_activity(
+        &self,
+        owner_pub_key: &[u8],
+    ) -> impl Iterator<Item = Result<ActivityRecord>> + Send + 'static {
+        self.account_activity
+            .scan_prefix(owner_pub_key)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+    }
+
     pub fn get_position_by_id(&self, position_id: &[u8]) -> Result<Option<PositionData>> {
         // println!("get position_id {}", hex::encode(position_id));
         match self.positions_by_id.get(format!("0x{}", hex::encode(position_id)).as_bytes())? {
@@ -142,33 +515,198 @@ impl Database {
         }
     }
 
-    // Public method with pagination
-    pub fn get_historical_positions(
+    pub fn size_on_disk(&self) -> Result<u64> {
+        Ok(self._db.size_on_disk()?)
+    }
+
+    /// Entry count of every tree. Walks each tree, so callers should cache it.
+    pub fn tree_sizes(&self) -> Result<Vec<(String, usize)>> {
+        self._db
+            .tree_names()
+            .into_iter()
+            .map(|name| {
+                let tree = self._db.open_tree(&name)?;
+                Ok((String::from_utf8_lossy(&name).into_owned(), tree.len()))
+            })
+            .collect()
+    }
+
+    // --- History Index ---
+
+    /// Rebuilds the index when it predates the current layout, including
+    /// records written before `history_index` existed.
+    fn backfill_history_index(&self) -> Result<()> {
+        let version = match self.protocol_state.get(HISTORY_INDEX_VERSION_KEY)? {
+            Some(data) => u64::from_be_bytes(data.as_ref().try_into()?),
+            None => 0,
+        };
+        if version == HISTORY_INDEX_VERSION {
+            return Ok(());
+        }
+        self.history_index.clear()?;
+        for item in self.historical_positions.iter() {
+            let (owner_pub_key, _) = item?;
+            for position in self.get_historical_positions_internal(&owner_pub_key)? {
+                self.index_historical_position(&owner_pub_key, &position)?;
+            }
+        }
+        self.protocol_state
+            .insert(HISTORY_INDEX_VERSION_KEY, &HISTORY_INDEX_VERSION.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn index_historical_position(
+        &self,
+        owner_pub_key: &[u8],
+        position: &HistoricalPosition,
+    ) -> Result<()> {
+        let entry = HistoryIndexEntry {
+            position_id: position.position.position_id.clone(),
+            closed_at: position.closed_at,
+            size: position.position.size.clone(),
+            realized_pnl: realized_pnl(position)?.to_string(),
+        };
+        let value = serde_json::to_vec(&entry)?;
+
+        for sort in [HistorySort::ClosedAt, HistorySort::Pnl, HistorySort::Size] {
+            let mut key = history_prefix(
+                owner_pub_key,
+                sort,
+                &position.status,
+                position.position.is_long,
+            );
+            key.extend_from_slice(&history_sort_key(sort, &entry)?);
+            self.history_index.insert(key, value.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Filtered, sorted paging over an owner's closed and liquidated positions.
+    ///
+    /// Status and direction select index prefixes, and the per-prefix
+    /// streams of the sort index are merged in sort order from the cursor
+    /// on. A range on the sort field (`from`/`to` by close time, `min_size`
+    /// by size) is a key range; other ranges are checked per row. At most
+    /// `HISTORY_SCAN_BUDGET` rows are read per page, so a selective filter
+    /// can return a short page whose `next_cursor` continues the scan.
+    pub fn query_historical_positions(
+        &self,
+        owner_pub_key: &[u8],
+        query: &HistoryQuery,
+        page_size: usize,
+    ) -> Result<PaginatedResponse<HistoricalPosition>> {
+        self.query_history(owner_pub_key, query, page_size, HISTORY_SCAN_BUDGET)
+    }
+
+    fn query_history(
         &self,
         owner_pub_key: &[u8],
-        cursor: Option<usize>,
+        query: &HistoryQuery,
         page_size: usize,
+        scan_budget: usize,
     ) -> Result<PaginatedResponse<HistoricalPosition>> {
-        let all_positions = self.get_historical_positions_internal(owner_pub_key)?;
-        let start = cursor.unwrap_or(0);
-        let end = std::cmp::min(start + page_size, all_positions.len());
-
-        if start >= all_positions.len() {
-            return Ok(PaginatedResponse {
-                items: vec![],
-                has_more: false,
-                next_cursor: None,
-            });
-        }
-
-        let items = all_positions[start..end].to_vec();
-        let has_more = end < all_positions.len();
-        let next_cursor = if has_more {
-            Some(end.to_string())
-        } else {
-            None
+        let statuses = match &query.status {
+            Some(status) => vec![status.clone()],
+            None => vec![PositionStatus::Closed, PositionStatus::Liquidated],
         };
+        let directions = match query.is_long {
+            Some(is_long) => vec![is_long],
+            None => vec![true, false],
+        };
+        let from = query.from.unwrap_or(0);
+        let to = query.to.unwrap_or(u64::MAX);
+        let min_size = query.min_size.unwrap_or_default();
 
+        let mut streams = Vec::new();
+        for status in &statuses {
+            for &is_long in &directions {
+                let prefix = history_prefix(owner_pub_key, query.sort, status, is_long);
+                let with_prefix = |suffix: &[u8]| [prefix.as_slice(), suffix].concat();
+                let (mut low, mut high) = match query.sort {
+                    HistorySort::ClosedAt => (
+                        with_prefix(&from.to_be_bytes()),
+                        with_prefix(&[&to.to_be_bytes()[..], &[0xff; 32]].concat()),
+                    ),
+                    HistorySort::Size => {
+                        let mut min = [0u8; 32];
+                        min_size.to_big_endian(&mut min);
+                        (with_prefix(&min), with_prefix(&[0xff; 64]))
+                    }
+                    HistorySort::Pnl => (prefix.clone(), with_prefix(&[0xff; 64])),
+                };
+                // Keyset paging: resume just past the previous page's last key.
+                let resume = query.after.as_ref().map(|after| with_prefix(after));
+                if let Some(resume) = &resume {
+                    if query.descending {
+                        high = high.min(resume.clone());
+                    } else {
+                        low = low.max(resume.clone());
+                    }
+                }
+                if low > high {
+                    continue;
+                }
+                let iter = self.history_index.range(low..=high).filter(move |item| {
+                    !matches!((item, &resume), (Ok((key, _)), Some(resume)) if key == resume)
+                });
+                let iter: IndexIter = if query.descending {
+                    Box::new(iter.rev())
+                } else {
+                    Box::new(iter)
+                };
+                streams.push((prefix.len(), iter.peekable()));
+            }
+        }
+
+        let matches = |entry: &HistoryIndexEntry| -> Result<bool> {
+            Ok(entry.closed_at >= from
+                && entry.closed_at <= to
+                && U256::from_dec_str(&entry.size)? >= min_size)
+        };
+        let mut page: Vec<(Vec<u8>, HistoryIndexEntry)> = Vec::with_capacity(page_size + 1);
+        let mut scanned = 0;
+        let mut last_scanned = None;
+        while page.len() <= page_size && scanned < scan_budget {
+            let Some((sort_key, value)) = pop_merged(&mut streams, query.descending)? else {
+                break;
+            };
+            scanned += 1;
+            let entry: HistoryIndexEntry = serde_json::from_slice(&value)?;
+            if matches(&entry)? {
+                page.push((sort_key.clone(), entry));
+            }
+            last_scanned = Some(sort_key);
+        }
+        // Out of budget before the page filled: resume after the last row read.
+        let budget_cursor = match last_scanned {
+            Some(key)
+                if page.len() <= page_size
+                    && streams.iter_mut().any(|(_, stream)| stream.peek().is_some()) =>
+            {
+                Some(hex::encode(key))
+            }
+            _ => None,
+        };
+
+        let has_more = page.len() > page_size || budget_cursor.is_some();
+        page.truncate(page_size);
+        let next_cursor = match (budget_cursor, page.last()) {
+            (Some(cursor), _) => Some(cursor),
+            (None, Some((sort_key, _))) if has_more => Some(hex::encode(sort_key)),
+            _ => None,
+        };
+        let mut items = Vec::with_capacity(page.len());
+        for (_, entry) in page {
+            let position_id = hex::decode(
+                entry
+                    .position_id
+                    .strip_prefix("0x")
+                    .unwrap_or(&entry.position_id),
+            )?;
+            if let Some(PositionData::Historical(position)) = self.get_position_by_id(&position_id)? {
+                items.push(position);
+            }
+        }
         Ok(PaginatedResponse {
             items,
             has_more,
@@ -176,9 +714,171 @@ impl Database {
         })
     }
 
+    // --- Protocol State ---
+
+    pub fn set_protocol_constants(&self, constants: &ProtocolConstants) -> Result<()> {
+        self.protocol_state
+            .insert(CONSTANTS_KEY, serde_json::to_vec(constants)?)?;
+        Ok(())
+    }
+
+    pub fn get_protocol_constants(&self) -> Result<Option<ProtocolConstants>> {
+        match self.protocol_state.get(CONSTANTS_KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_mark_price(&self, mark_price: &MarkPrice) -> Result<()> {
+        self.protocol_state
+            .insert(MARK_PRICE_KEY, serde_json::to_vec(mark_price)?)?;
+        Ok(())
+    }
+
+    pub fn get_mark_price(&self) -> Result<Option<MarkPrice>> {
+        match self.protocol_state.get(MARK_PRICE_KEY)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // --- Protocol Stats ---
+
+    pub fn get_protocol_totals(&self) -> Result<ProtocolTotals> {
+        match self.protocol_stats.get(TOTALS_KEY)? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(ProtocolTotals::default()),
+        }
+    }
+
+    pub fn update_protocol_totals(&self, update: impl FnOnce(&mut ProtocolTotals)) -> Result<()> {
+        let mut totals = self.get_protocol_totals()?;
+        update(&mut totals);
+        self.protocol_stats
+            .insert(TOTALS_KEY, serde_json::to_vec(&totals)?)?;
+        Ok(())
+    }
+
+    pub fn add_hourly_volume(&self, hour: u64, volume: U256) -> Result<()> {
+        let current = match self.hourly_volume.get(hour.to_be_bytes())? {
+            Some(data) => U256::from_big_endian(&data),
+            None => U256::zero(),
+        };
+        let mut bytes = [0u8; 32];
+        (current + volume).to_big_endian(&mut bytes);
+        self.hourly_volume.insert(hour.to_be_bytes(), &bytes)?;
+        Ok(())
+    }
+
+    /// Sums the hourly volume buckets from `from_hour` onwards.
+    pub fn get_volume_since(&self, from_hour: u64) -> Result<U256> {
+        let mut total = U256::zero();
+        for item in self.hourly_volume.range(from_hour.to_be_bytes()..) {
+            let (_, value) = item?;
+            total += U256::from_big_endian(&value);
+        }
+        Ok(total)
+    }
+
+    // --- Price History ---
+
+    /// Records an oracle update in the time series, folds it into every
+    /// candle interval and advances the mark price if it is the newest.
+    /// The history entry and candles are written in one transaction, keyed
+    /// by the log's position, so a replayed log is skipped rather than
+    /// counted twice.
+    pub fn record_price_update(
+        &self,
+        update: &MarkPrice,
+        block_number: u64,
+        log_index: u64,
+    ) -> Result<()> {
+        let key = price_history_key(update.timestamp, block_number, log_index);
+        let price = U256::from_dec_str(&update.price)?;
+        let value = serde_json::to_vec(update)?;
+        let applied = transaction_result((&self.price_history, &self.price_candles).transaction(
+            |(history, candles)| {
+                if history.get(&key)?.is_some() {
+                    return Ok(false);
+                }
+                history.insert(key.as_slice(), value.as_slice())?;
+                for interval in CandleInterval::ALL {
+                    let candle_key = candle_key(interval, interval.bucket_start(update.timestamp));
+                    let candle = match candles.get(&candle_key)? {
+                        Some(data) => Some(serde_json::from_slice(&data).map_err(abort)?),
+                        None => None,
+                    };
+                    let candle = fold_candle(candle, interval, update, price).map_err(abort)?;
+                    candles.insert(candle_key, serde_json::to_vec(&candle).map_err(abort)?)?;
+                }
+                Ok(true)
+            },
+        ))?;
+        if !applied {
+            return Ok(());
+        }
+
+        let is_newest = match self.get_mark_price()? {
+            Some(current) => update.timestamp >= current.timestamp,
+            None => true,
+        };
+        if is_newest {
+            self.set_mark_price(update)?;
+        }
+        Ok(())
+    }
+
+    /// The latest oracle update at or before the given log position.
+    pub fn price_at(
+        &self,
+        timestamp: u64,
+        block_number: u64,
+        log_index: u64,
+    ) -> Result<Option<MarkPrice>> {
+        match self
+            .price_history
+            .range(..=price_history_key(timestamp, block_number, log_index))
+            .next_back()
+        {
+            Some(item) => Ok(Some(serde_json::from_slice(&item?.1)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_price_history(&self, from: u64, to: u64, limit: usize) -> Result<Vec<MarkPrice>> {
+        self.price_history
+            .range(price_history_key(from, 0, 0)..=price_history_key(to, u64::MAX, u64::MAX))
+            .take(limit)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+            .collect()
+    }
+
+    pub fn get_candles(
+        &self,
+        interval: CandleInterval,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<Candle>> {
+        let start = candle_key(interval, interval.bucket_start(from));
+        let end = candle_key(interval, to);
+        self.price_candles
+            .range(start..=end)
+            .take(limit)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+            .collect()
+    }
+
     // --- Note Management ---
 
-    pub fn add_unspent_note(&self, note: &UnspentNote) -> Result<()> {
+    /// Returns `true` if it is the receiver's only unspent note.
+    pub fn add_unspent_note(&self, note: &UnspentNote) -> Result<bool> {
         let receiver_hash_bytes = hex::decode(
             note.note
                 .receiver_hash
@@ -189,34 +889,144 @@ impl Database {
         notes.push(note.clone());
         self.unspent_notes
             .insert(receiver_hash_bytes, serde_json::to_vec(&notes)?)?;
-        println!("Note added {}", format!("{}", note.note_id));
-        Ok(())
+        println!("Note added {}", note.note_id);
+        Ok(notes.len() == 1)
     }
 
-    pub fn remove_unspent_note(&self, note_id_to_remove: &[u8]) -> Result<()> {
-        println!(
-            "Removing Note {}",
-            format!("0x{}", hex::encode(note_id_to_remove))
-        );
+    /// Removes a note from the unspent set and returns it, if it was known.
+    pub fn remove_unspent_note(&self, note_id_to_remove: &[u8]) -> Result<Option<UnspentNote>> {
+        println!("Removing Note 0x{}", hex::encode(note_id_to_remove));
         for item in self.unspent_notes.iter() {
             let (key, value) = item?;
             let mut notes: Vec<UnspentNote> = serde_json::from_slice(&value)?;
-            let original_len = notes.len();
-            notes.retain(|n| n.note_id != format!("0x{}", hex::encode(note_id_to_remove)));
-            if notes.len() < original_len {
+            let note_id = format!("0x{}", hex::encode(note_id_to_remove));
+            if let Some(index) = notes.iter().position(|n| n.note_id == note_id) {
+                let removed = notes.remove(index);
                 self.unspent_notes
                     .insert(key, serde_json::to_vec(&notes)?)?;
                 println!(
-                    "Note retained {} now notes length {}",
-                    format!("0x{}", hex::encode(note_id_to_remove)),
+                    "Note retained 0x{} now notes length {}",
+                    hex::encode(note_id_to_remove),
                     notes.len()
                 );
-                return Ok(());
+                return Ok(Some(removed));
             }
         }
+        Ok(None)
+    }
+
+    // --- Spent Notes ---
+
+    pub fn add_spent_note(&self, spent: &SpentNote) -> Result<()> {
+        let receiver_hash = &spent.note.note.receiver_hash;
+        let mut key = hex::decode(receiver_hash.strip_prefix("0x").unwrap_or(receiver_hash))?;
+        let note_id = &spent.note.note_id;
+        key.extend(hex::decode(note_id.strip_prefix("0x").unwrap_or(note_id))?);
+        self.spent_notes.insert(key, serde_json::to_vec(spent)?)?;
+        Ok(())
+    }
+
+    pub fn get_spent_notes(&self, receiver_hash: &[u8]) -> Result<Vec<SpentNote>> {
+        self.spent_notes
+            .scan_prefix(receiver_hash)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+            .collect()
+    }
+
+    // --- Nullifiers ---
+
+    pub fn add_spent_nullifier(&self, spent: &SpentNullifier) -> Result<()> {
+        let nullifier = hex::decode(spent.nullifier.strip_prefix("0x").unwrap_or(&spent.nullifier))?;
+        self.spent_nullifiers
+            .insert(nullifier, serde_json::to_vec(spent)?)?;
+        Ok(())
+    }
+
+    pub fn get_spent_nullifier(&self, nullifier: &[u8]) -> Result<Option<SpentNullifier>> {
+        match self.spent_nullifiers.get(nullifier)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// First block of the indexer's current contiguous run; earlier runs
+    /// leave gaps, so spends before it may be missing.
+    pub fn set_nullifiers_indexed_from(&self, block: u64) -> Result<()> {
+        self.protocol_state
+            .insert(NULLIFIERS_INDEXED_FROM_KEY, &block.to_be_bytes())?;
         Ok(())
     }
 
+    pub fn get_nullifiers_indexed_from(&self) -> Result<Option<u64>> {
+        match self.protocol_state.get(NULLIFIERS_INDEXED_FROM_KEY)? {
+            Some(data) => Ok(Some(u64::from_be_bytes(data.as_ref().try_into()?))),
+            None => Ok(None),
+        }
+    }
+
+    // --- Note Feed ---
+
+    pub fn record_note_event(&self, entry: &NoteFeedEntry) -> Result<()> {
+        self.note_feed.insert(
+            note_feed_key(entry.block_number, entry.log_index),
+            serde_json::to_vec(entry)?,
+        )?;
+        Ok(())
+    }
+
+    /// Feed entries strictly after `after` (block, log index), or from the
+    /// start when `None`, up to and including `to_block`.
+    pub fn get_note_feed(
+        &self,
+        after: Option<(u64, u64)>,
+        to_block: u64,
+        limit: usize,
+    ) -> Result<Vec<NoteFeedEntry>> {
+        let end = note_feed_key(to_block, u64::MAX);
+        let range = match after {
+            Some((block, log_index)) => {
+                let start = note_feed_key(block, log_index);
+                self.note_feed
+                    .range((Bound::Excluded(start), Bound::Included(end)))
+            }
+            None => self.note_feed.range(..=end),
+        };
+        range
+            .take(limit)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+            .collect()
+    }
+
+    /// The (block, log index) of the newest feed entry.
+    pub fn note_feed_head(&self) -> Result<Option<(u64, u64)>> {
+        match self.note_feed.last()? {
+            Some((key, _)) => Ok(Some((
+                u64::from_be_bytes(key[..8].try_into()?),
+                u64::from_be_bytes(key[8..16].try_into()?),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Receiver hashes that currently hold at least one unspent note.
+    pub fn active_receiver_hashes(&self) -> Result<Vec<[u8; 32]>> {
+        let mut hashes = Vec::new();
+        for item in self.unspent_notes.iter() {
+            let (key, value) = item?;
+            let notes: Vec<UnspentNote> = serde_json::from_slice(&value)?;
+            if !notes.is_empty() {
+                hashes.push(key.as_ref().try_into()?);
+            }
+        }
+        Ok(hashes)
+    }
+
     pub fn get_unspent_notes(&self, receiver_hash: &[u8]) -> Result<Vec<UnspentNote>> {
         match self.unspent_notes.get(receiver_hash)? {
             Some(data) => Ok(serde_json::from_slice(&data)?),
@@ -224,12 +1034,374 @@ impl Database {
         }
     }
 
-    // pub fn set_user_metadata(&self, owner_pub_key: &[u8], encrypted_blob: Vec<u8>) -> Result<()> {
-    //     self.user_metadata.insert(owner_pub_key, encrypted_blob)?;
-    //     Ok(())
-    // }
+    // --- Metadata Slots ---
+
+    /// Moves blobs from the single-value `user_metadata` tree into the
+    /// `default` slot at version 1. A blob whose owner already has a
+    /// `default` slot is left in place rather than dropped.
+    fn migrate_legacy_metadata(&self) -> Result<()> {
+        for item in self.user_metadata.iter() {
+            let (owner_pub_key, data) = item?;
+            let key = metadata_key(&owner_pub_key, DEFAULT_METADATA_SLOT);
+            let blob = serde_json::to_vec(&MetadataBlob {
+                version: 1,
+                updated_at: 0,
+                data: hex::encode(&data),
+            })?;
+            self.init_metadata_slot_count(&owner_pub_key)?;
+            let copied = transaction_result(
+                (&self.metadata_slots, &self.metadata_slot_counts).transaction(|(slots, counts)| {
+                    if slots.get(&key)?.is_some() {
+                        return Ok(false);
+                    }
+                    let count = read_slot_count(counts.get(&owner_pub_key)?).map_err(abort)?;
+                    slots.insert(key.as_slice(), blob.as_slice())?;
+                    counts.insert(owner_pub_key.as_ref(), &(count + 1).to_be_bytes())?;
+                    Ok(true)
+                }),
+            )?;
+            if copied {
+                self.user_metadata.remove(owner_pub_key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seeds an owner's slot counter from a scan of their slots. Counters
+    /// are kept inside the write transaction, so this only matters for
+    /// slots written before they existed.
+    fn init_metadata_slot_count(&self, owner_pub_key: &[u8]) -> Result<()> {
+        if self.metadata_slot_counts.contains_key(owner_pub_key)? {
+            return Ok(());
+        }
+        let count = self.metadata_slots.scan_prefix(owner_pub_key).count() as u64;
+        // Losing the race means another writer already seeded or bumped it.
+        let _ = self.metadata_slot_counts.compare_and_swap(
+            owner_pub_key,
+            None as Option<&[u8]>,
+            Some(&count.to_be_bytes()[..]),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_metadata_slot(
+        &self,
+        owner_pub_key: &[u8],
+        slot: &str,
+    ) -> Result<Option<MetadataBlob>> {
+        match self.metadata_slots.get(metadata_key(owner_pub_key, slot))? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Slot names and their current blobs for one owner.
+    pub fn list_metadata_slots(&self, owner_pub_key: &[u8]) -> Result<Vec<(String, MetadataBlob)>> {
+        self.metadata_slots
+            .scan_prefix(owner_pub_key)
+            .map(|item| {
+                let (key, value) = item?;
+                let slot = String::from_utf8(key[owner_pub_key.len()..].to_vec())?;
+                Ok((slot, serde_json::from_slice(&value)?))
+            })
+            .collect()
+    }
+
+    /// Writes a slot if it matches `precondition`. The replaced blob is kept
+    /// in `metadata_history`, bounded to `history_limit` versions, in the same
+    /// transaction as the check and the write.
+    pub fn write_metadata_slot(
+        &self,
+        owner_pub_key: &[u8],
+        slot: &str,
+        precondition: SlotPrecondition,
+        data: &[u8],
+        limits: MetadataLimits,
+    ) -> Result<MetadataWrite> {
+        let key = metadata_key(owner_pub_key, slot);
+        let history_prefix = metadata_history_prefix(owner_pub_key, slot);
+        let history_key = |version: u64| [history_prefix.as_slice(), &version.to_be_bytes()].concat();
+        self.init_metadata_slot_count(owner_pub_key)?;
+        let data = hex::encode(data);
+
+        let trees = (&self.metadata_slots, &self.metadata_history, &self.metadata_slot_counts);
+        transaction_result(trees.transaction(
+            |(slots, history, counts)| {
+                let current = slots.get(&key)?;
+                let previous: Option<MetadataBlob> = match &current {
+                    Some(raw) => Some(serde_json::from_slice(raw).map_err(abort)?),
+                    None => None,
+                };
+                let current_version = previous.as_ref().map(|blob| blob.version);
+                let matches = match precondition {
+                    SlotPrecondition::Any => true,
+                    SlotPrecondition::Missing => current_version.is_none(),
+                    SlotPrecondition::Version(version) => current_version == Some(version),
+                };
+                if !matches {
+                    return Ok(MetadataWrite::Conflict(current_version));
+                }
+                if previous.is_none() {
+                    let count = read_slot_count(counts.get(owner_pub_key)?).map_err(abort)?;
+                    if count >= limits.max_slots as u64 {
+                        return Ok(MetadataWrite::TooManySlots);
+                    }
+                    counts.insert(owner_pub_key, &(count + 1).to_be_bytes())?;
+                }
+
+                let blob = MetadataBlob {
+                    version: current_version.unwrap_or(0) + 1,
+                    updated_at: stats::current_timestamp(),
+                    data: data.clone(),
+                };
+                slots.insert(key.as_slice(), serde_json::to_vec(&blob).map_err(abort)?)?;
+                if let (Some(previous), Some(raw)) = (previous, current) {
+                    history.insert(history_key(previous.version), raw)?;
+                    // Versions are consecutive, so exactly one falls out of the window.
+                    if let Some(evicted) = previous.version.checked_sub(limits.history_limit as u64) {
+                        history.remove(history_key(evicted))?;
+                    }
+                }
+                Ok(MetadataWrite::Written(blob.version))
+            },
+        ))
+    }
+
+    /// Previous versions of a slot, newest first.
+    pub fn get_metadata_history(
+        &self,
+        owner_pub_key: &[u8],
+        slot: &str,
+    ) -> Result<Vec<MetadataBlob>> {
+        self.metadata_history
+            .scan_prefix(metadata_history_prefix(owner_pub_key, slot))
+            .rev()
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(serde_json::from_slice(&value)?)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary() -> Database {
+        Database::from_db(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    fn update(price: u64, timestamp: u64) -> MarkPrice {
+        MarkPrice { price: price.to_string(), timestamp }
+    }
+
+    /// Opens and closes a position; `id` fills the last byte of its id.
+    fn close(db: &Database, id: u8, size: u64, pnl: i64, fee: u64, closed_at: u64) {
+        let position_id = format!("0x{}", hex::encode([id; 32]));
+        let position = Position {
+            position_id: position_id.clone(),
+            is_long: true,
+            entry_price: "100".to_string(),
+            margin: "1000".to_string(),
+            size: size.to_string(),
+        };
+        db.add_open_position(b"owner", position).unwrap();
+        db.move_to_historical(
+            &[id; 32],
+            PositionStatus::Closed,
+            pnl.to_string(),
+            fee.to_string(),
+            "0x0".to_string(),
+            closed_at,
+        )
+        .unwrap();
+    }
+
+    fn query(sort: HistorySort) -> HistoryQuery {
+        HistoryQuery {
+            status: None,
+            is_long: None,
+            from: None,
+            to: None,
+            min_size: None,
+            sort,
+            descending: false,
+            after: None,
+        }
+    }
+
+    /// Follows `next_cursor` to the end and returns the sizes in page order.
+    fn all_pages(db: &Database, mut query: HistoryQuery, page_size: usize) -> Vec<String> {
+        let mut sizes = Vec::new();
+        loop {
+            let page = db.query_historical_positions(b"owner", &query, page_size).unwrap();
+            sizes.extend(page.items.iter().map(|p| p.position.size.clone()));
+            match page.next_cursor {
+                Some(cursor) => query.after = Some(hex::decode(cursor).unwrap()),
+                None => return sizes,
+            }
+        }
+    }
+
+    #[test]
+    fn keyset_pages_cover_every_position_once() {
+        let db = temporary();
+        for id in 1..=5u8 {
+            close(&db, id, id as u64, 0, 0, 100);
+        }
+        let mut by_time = query(HistorySort::ClosedAt);
+        by_time.descending = true;
+        assert_eq!(all_pages(&db, by_time, 2), ["5", "4", "3", "2", "1"]);
+    }
+
+    #[test]
+    fn min_size_is_a_range_on_the_size_index() {
+        let db = temporary();
+        close(&db, 1, 10, 0, 0, 100);
+        close(&db, 2, 30, 0, 0, 200);
+        close(&db, 3, 20, 0, 0, 300);
+
+        let mut by_size = query(HistorySort::Size);
+        by_size.min_size = Some(U256::from(20));
+        assert_eq!(all_pages(&db, by_size, 1), ["20", "30"]);
+
+        let mut by_time = query(HistorySort::ClosedAt);
+        by_time.min_size = Some(U256::from(20));
+        assert_eq!(all_pages(&db, by_time, 1), ["30", "20"]);
+    }
+
+    #[test]
+    fn pnl_sort_ranks_net_of_fee_within_a_time_range() {
+        let db = temporary();
+        close(&db, 1, 1, 50, 45, 100); // nets 5
+        close(&db, 2, 2, 20, 0, 200); // nets 20
+        close(&db, 3, 3, 100, 0, 900); // outside the range
+        close(&db, 4, 4, 10, 0, 300); // nets 10
+
+        let mut by_pnl = query(HistorySort::Pnl);
+        by_pnl.to = Some(500);
+        assert_eq!(all_pages(&db, by_pnl, 1), ["1", "4", "2"]);
+    }
+
+    #[test]
+    fn selective_filter_pages_within_the_scan_budget() {
+        let db = temporary();
+        for id in 1..=6u8 {
+            let size = if id == 1 { 50 } else { 1 };
+            close(&db, id, size, 0, 0, 100 + id as u64);
+        }
+        let mut by_time = query(HistorySort::ClosedAt);
+        by_time.min_size = Some(U256::from(50));
+        by_time.descending = true;
+
+        // Newest first, the only match is the sixth row, past a budget of 4.
+        let first = db.query_history(b"owner", &by_time, 10, 4).unwrap();
+        assert!(first.items.is_empty() && first.has_more);
+        by_time.after = Some(hex::decode(first.next_cursor.unwrap()).unwrap());
+        let second = db.query_history(b"owner", &by_time, 10, 4).unwrap();
+        let sizes: Vec<_> = second.items.iter().map(|p| p.position.size.as_str()).collect();
+        assert_eq!(sizes, ["50"]);
+        assert!(!second.has_more);
+    }
+
+    const LIMITS: MetadataLimits = MetadataLimits {
+        max_slots: 2,
+        history_limit: 2,
+    };
+
+    #[test]
+    fn metadata_write_checks_the_precondition() {
+        let db = temporary();
+        let write = |precondition, data: &[u8]| {
+            db.write_metadata_slot(b"owner", "a", precondition, data, LIMITS).unwrap()
+        };
+        assert_eq!(write(SlotPrecondition::Missing, b"1"), MetadataWrite::Written(1));
+        assert_eq!(write(SlotPrecondition::Missing, b"2"), MetadataWrite::Conflict(Some(1)));
+        assert_eq!(write(SlotPrecondition::Version(2), b"2"), MetadataWrite::Conflict(Some(1)));
+        assert_eq!(write(SlotPrecondition::Version(1), b"2"), MetadataWrite::Written(2));
+        assert_eq!(write(SlotPrecondition::Any, b"3"), MetadataWrite::Written(3));
+    }
+
+    #[test]
+    fn metadata_history_keeps_the_last_versions() {
+        let db = temporary();
+        for data in [b"1", b"2", b"3", b"4"] {
+            db.write_metadata_slot(b"owner", "a", SlotPrecondition::Any, data, LIMITS)
+                .unwrap();
+        }
+        let versions: Vec<u64> = db
+            .get_metadata_history(b"owner", "a")
+            .unwrap()
+            .iter()
+            .map(|blob| blob.version)
+            .collect();
+        assert_eq!(versions, [3, 2]);
+    }
+
+    #[test]
+    fn metadata_slot_limit_counts_slots_written_before_the_counter() {
+        let db = temporary();
+        let blob = |version| serde_json::to_vec(&MetadataBlob { version, updated_at: 0, data: String::new() }).unwrap();
+        db.metadata_slots.insert(metadata_key(b"owner", "a"), blob(1)).unwrap();
+        let write = |slot| db.write_metadata_slot(b"owner", slot, SlotPrecondition::Missing, b"1", LIMITS).unwrap();
+        assert_eq!(write("b"), MetadataWrite::Written(1));
+        assert_eq!(write("c"), MetadataWrite::TooManySlots);
+        assert_eq!(db.list_metadata_slots(b"owner").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn legacy_metadata_is_kept_when_the_default_slot_exists() {
+        let db = temporary();
+        db.user_metadata.insert(b"copied", b"legacy").unwrap();
+        db.user_metadata.insert(b"taken", b"legacy").unwrap();
+        db.write_metadata_slot(b"taken", DEFAULT_METADATA_SLOT, SlotPrecondition::Any, b"new", LIMITS)
+            .unwrap();
+        db.migrate_legacy_metadata().unwrap();
+
+        let copied = db.get_metadata_slot(b"copied", DEFAULT_METADATA_SLOT).unwrap().unwrap();
+        assert_eq!(copied.data, hex::encode(b"legacy"));
+        assert!(!db.user_metadata.contains_key(b"copied").unwrap());
+        assert!(db.user_metadata.contains_key(b"taken").unwrap());
+        assert_eq!(db.metadata_slot_counts.get(b"copied").unwrap().unwrap().as_ref(), 1u64.to_be_bytes());
+    }
 
-    // pub fn get_user_metadata(&self, owner_pub_key: &[u8]) -> Result<Option<Vec<u8>>> {
-    //     Ok(self.user_metadata.get(owner_pub_key)?.map(|iv| iv.to_vec()))
-    // }
+    #[test]
+    fn same_second_price_updates_are_all_kept() {
+        let db = temporary();
+        db.record_price_update(&update(100, 60), 7, 0).unwrap();
+        db.record_price_update(&update(105, 60), 7, 1).unwrap();
+        db.record_price_update(&update(95, 60), 8, 0).unwrap();
+
+        let history = db.get_price_history(60, 60, 10).unwrap();
+        let prices: Vec<_> = history.iter().map(|p| p.price.as_str()).collect();
+        assert_eq!(prices, ["100", "105", "95"]);
+        let candle = &db.get_candles(CandleInterval::OneMinute, 0, 60, 10).unwrap()[0];
+        assert_eq!(candle.num_updates, 3);
+        assert_eq!((candle.high.as_str(), candle.low.as_str()), ("105", "95"));
+    }
+
+    #[test]
+    fn price_at_ignores_later_updates_in_the_same_block() {
+        let db = temporary();
+        db.record_price_update(&update(100, 60), 7, 0).unwrap();
+        db.record_price_update(&update(80, 72), 9, 3).unwrap();
+
+        let at = |block, log_index| db.price_at(72, block, log_index).unwrap().unwrap().price;
+        assert_eq!(at(9, 2), "100");
+        assert_eq!(at(9, 3), "80");
+        assert!(db.price_at(59, u64::MAX, u64::MAX).unwrap().is_none());
+    }
+
+    #[test]
+    fn replayed_price_update_is_applied_once() {
+        let db = temporary();
+        db.record_price_update(&update(100, 60), 7, 0).unwrap();
+        db.record_price_update(&update(100, 60), 7, 0).unwrap();
+
+        assert_eq!(db.get_price_history(0, 120, 10).unwrap().len(), 1);
+        for interval in CandleInterval::ALL {
+            assert_eq!(db.get_candles(interval, 0, 120, 10).unwrap()[0].num_updates, 1);
+        }
+    }
 }
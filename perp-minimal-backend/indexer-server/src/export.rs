@@ -0,0 +1,144 @@
+// src/export.rs
+//! Row formatting for account statement exports.
+use crate::models::ActivityRecord;
+use ethers::{
+    types::{I256, U256},
+    utils::format_units,
+};
+
+const TOKEN_DECIMALS: u32 = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "csv" => Some(ExportFormat::Csv),
+            "ndjson" => Some(ExportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+pub const CSV_HEADER: &str = "timestamp,datetime_utc,block_number,tx_hash,kind,position_id,direction,size,size_human,entry_price,entry_price_human,amount,amount_human,fee,fee_human,realized_pnl,realized_pnl_human\n";
+
+/// Formats a raw 18-decimal integer string (signed or unsigned) in token units.
+fn human(raw: &Option<String>) -> Option<String> {
+    let raw = raw.as_ref()?;
+    let formatted = match U256::from_dec_str(raw) {
+        Ok(value) => format_units(value, TOKEN_DECIMALS),
+        Err(_) => format_units(I256::from_dec_str(raw).ok()?, TOKEN_DECIMALS),
+    };
+    formatted.ok()
+}
+
+/// ISO-8601 UTC timestamp (civil-from-days, no calendar dependency).
+fn format_utc(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        (secs % 3_600) / 60,
+        secs % 60
+    )
+}
+
+pub fn csv_row(record: &ActivityRecord) -> String {
+    let kind = serde_json::to_value(record.kind)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let direction = match record.is_long {
+        Some(true) => "long",
+        Some(false) => "short",
+        None => "",
+    };
+    let fields = [
+        record.timestamp.to_string(),
+        format_utc(record.timestamp),
+        record.block_number.to_string(),
+        record.tx_hash.clone(),
+        kind,
+        record.position_id.clone().unwrap_or_default(),
+        direction.to_string(),
+        record.size.clone().unwrap_or_default(),
+        human(&record.size).unwrap_or_default(),
+        record.entry_price.clone().unwrap_or_default(),
+        human(&record.entry_price).unwrap_or_default(),
+        record.amount.clone().unwrap_or_default(),
+        human(&record.amount).unwrap_or_default(),
+        record.fee.clone().unwrap_or_default(),
+        human(&record.fee).unwrap_or_default(),
+        record.realized_pnl.clone().unwrap_or_default(),
+        human(&record.realized_pnl).unwrap_or_default(),
+    ];
+    let mut row = fields.join(",");
+    row.push('\n');
+    row
+}
+
+pub fn ndjson_row(record: &ActivityRecord) -> String {
+    let mut value = serde_json::to_value(record).unwrap_or_default();
+    if let Some(object) = value.as_object_mut() {
+        object.insert("datetime_utc".into(), format_utc(record.timestamp).into());
+        object.insert("size_human".into(), human(&record.size).into());
+        object.insert("entry_price_human".into(), human(&record.entry_price).into());
+        object.insert("amount_human".into(), human(&record.amount).into());
+        object.insert("fee_human".into(), human(&record.fee).into());
+        object.insert("realized_pnl_human".into(), human(&record.realized_pnl).into());
+    }
+    let mut row = value.to_string();
+    row.push('\n');
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc_matches_the_civil_calendar() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(946_684_799), "1999-12-31T23:59:59Z");
+        assert_eq!(format_utc(1_709_208_000), "2024-02-29T12:00:00Z");
+    }
+
+    #[test]
+    fn format_utc_handles_century_leap_years() {
+        // 2000 is divisible by 400 and has a leap day; 2100 is not and does not.
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(format_utc(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+}
@@ -2,26 +2,162 @@
 use crate::{
     config::Config,
     database::Database,
-    models::{Position, PositionStatus, UnspentNote},
+    leaderboard,
+    models::{
+        ActivityKind, ActivityRecord, MarkPrice, NoteEventKind, NoteFeedEntry, Position,
+        NullifierSource, PositionStatus, ProtocolConstants, SpentNote, SpentNullifier,
+        UnspentNote,
+    },
+    metrics, note_digest, stats,
+    status::SyncStatus,
 };
 use anyhow::Result;
-use ethers::prelude::*;
-use std::sync::Arc;
+use ethers::{abi::AbiDecode, prelude::*};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use tokio::time::{sleep, Duration};
 
 abigen!(
     PrivacyProxy, "abi/PrivacyProxy.json";
     ClearingHouseV2, "abi/ClearingHouseV2.json";
     TokenPoolV2, "abi/TokenPool.json";
+    Oracle, "abi/Oracle.json";
+    Erc20, r#"[event Transfer(address indexed from, address indexed to, uint256 value)]"#;
 );
 
 const BLOCK_CHUNK_SIZE: u64 = 2_000;
 const DELAY_BETWEEN_CHUNKS_MS: u64 = 500; // 0.5 seconds
+const BLOCK_TIMESTAMP_CACHE_SIZE: usize = 1_024;
+const BLOCK_TIMESTAMP_MAX_BACKOFF_MS: u64 = 10_000;
+/// Longer than the log filter polling interval, see `SyncStatus::checkpoint`.
+const SYNC_CHECKPOINT_INTERVAL_SECS: u64 = 15;
+const RESUBSCRIBE_DELAY_SECS: u64 = 5;
+/// Deposit transactions remembered while their commitments wait for the next head.
+const RECENT_DEPOSITS_SIZE: usize = 1_024;
+const REALTIME_STREAMS: [&str; 15] = [
+    "PrivatePositionOpened",
+    "PublicPositionOpened",
+    "PositionClosed",
+    "PositionLiquidated",
+    "MarginAdded",
+    "MarginRemoved",
+    "PriceUpdated",
+    "PrivateCollateralDeposited",
+    "PrivateCollateralWithdrawn",
+    "PublicCollateralDeposited",
+    "PublicCollateralWithdrawn",
+    "NoteCreated",
+    "NoteClaimed",
+    "CommitmentInserted",
+    "PoolDeposit",
+];
+
+/// Where and when an event was emitted.
+struct EventContext {
+    block_number: u64,
+    tx_hash: H256,
+    log_index: u64,
+    timestamp: u64,
+}
+
+impl EventContext {
+    fn activity(&self, kind: ActivityKind) -> ActivityRecord {
+        ActivityRecord {
+            kind,
+            timestamp: self.timestamp,
+            block_number: self.block_number,
+            tx_hash: format!("{:#x}", self.tx_hash),
+            position_id: None,
+            is_long: None,
+            size: None,
+            entry_price: None,
+            amount: None,
+            fee: None,
+            realized_pnl: None,
+        }
+    }
+}
+
+/// Caches block timestamps so events from the same block cost one RPC call.
+#[derive(Default)]
+struct BlockTimestamps {
+    cache: HashMap<u64, u64>,
+}
+
+async fn fetch_block_timestamp(provider: &Provider<Ws>, block_number: u64) -> Result<u64> {
+    let block = metrics::rpc("eth_getBlockByNumber", provider.get_block(block_number)).await?;
+    match block {
+        Some(block) => Ok(block.timestamp.as_u64()),
+        None => Err(anyhow::anyhow!("block {} not found", block_number)),
+    }
+}
+
+impl BlockTimestamps {
+    /// Retries until the node returns the block: every time-bucketed figure
+    /// depends on it, so there is no wall-clock fallback.
+    async fn timestamp(&mut self, provider: &Provider<Ws>, block_number: u64) -> u64 {
+        if let Some(timestamp) = self.cache.get(&block_number) {
+            return *timestamp;
+        }
+        let mut backoff_ms = 250;
+        let timestamp = loop {
+            match fetch_block_timestamp(provider, block_number).await {
+                Ok(timestamp) => break timestamp,
+                Err(e) => {
+                    eprintln!(
+                        "[Indexer ERROR] Failed to fetch timestamp of block {}, retrying in {}ms: {}",
+                        block_number, backoff_ms, e
+                    );
+                    sleep(Duration::from_millis(backoff_ms)).await;
+                    backoff_ms = (backoff_ms * 2).min(BLOCK_TIMESTAMP_MAX_BACKOFF_MS);
+                }
+            }
+        };
+        if self.cache.len() >= BLOCK_TIMESTAMP_CACHE_SIZE {
+            self.cache.clear();
+        }
+        self.cache.insert(block_number, timestamp);
+        timestamp
+    }
+
+    async fn context(&mut self, provider: &Provider<Ws>, meta: &LogMeta) -> EventContext {
+        let block_number = meta.block_number.as_u64();
+        let timestamp = self.timestamp(provider, block_number).await;
+        EventContext {
+            block_number,
+            tx_hash: meta.transaction_hash,
+            log_index: meta.log_index.as_u64(),
+            timestamp,
+        }
+    }
+}
+
+/// A backfilled log of any indexed kind, so a chunk can be applied in
+/// `(block_number, log_index)` order.
+enum BackfillEvent {
+    PriceUpdated(oracle::PriceUpdatedFilter),
+    PositionOpened(privacy_proxy::PositionOpenedFilter),
+    PositionClosed(clearing_house_v2::PositionClosedFilter),
+    PositionLiquidated(clearing_house_v2::PositionLiquidatedFilter),
+    NoteCreated(token_pool_v2::NoteCreatedFilter),
+    NoteClaimed(token_pool_v2::NoteClaimedFilter),
+    MarginAdded(clearing_house_v2::MarginAddedFilter),
+    MarginRemoved(clearing_house_v2::MarginRemovedFilter),
+    PrivateDeposit(privacy_proxy::CollateralDepositedFilter),
+    PrivateWithdrawal(privacy_proxy::CollateralWithdrawnFilter),
+    PublicDeposit(clearing_house_v2::CollateralDepositedFilter),
+    PublicWithdrawal(clearing_house_v2::CollateralWithdrawnFilter),
+    /// Handled from the transaction input, see `handle_commitment_inserted`.
+    CommitmentInserted,
+}
 
 pub async fn run_indexer(
     config: Arc<Config>,
     db: Arc<Database>,
     provider: Arc<Provider<Ws>>,
+    sync: Arc<SyncStatus>,
 ) -> Result<()> {
     // Contract Instances
     // println!("Config {:#?}" , config);
@@ -33,11 +169,17 @@ pub async fn run_indexer(
     let tp_address: Address = config.token_pool_address.parse()?;
     let token_pool_contract = TokenPoolV2::new(tp_address, Arc::clone(&provider));
     let token_address: Address = config.token_address.parse()?;
+    let token_contract = Erc20::new(token_address, Arc::clone(&provider));
+    let oracle_address = ch_contract.oracle().call().await?;
+    let oracle_contract = Oracle::new(oracle_address, Arc::clone(&provider));
+
+    load_protocol_constants(&db, &ch_contract).await?;
+    note_digest::ensure(&db)?;
 
     println!("[Indexer] Listening for events from all relevant contracts...");
 
     // Get the latest block on the chain
-    let mut from_block = match provider.get_block_number().await {
+    let from_block = match metrics::rpc("eth_blockNumber", provider.get_block_number()).await {
         Ok(block_num) => block_num.as_u64(),
         Err(e) => {
             // This will print the *actual* root cause before crashing
@@ -49,135 +191,755 @@ pub async fn run_indexer(
             return Err(e.into());
         }
     };
-    let latest_block = from_block.clone(); // Temp fix: Todo take from block from config
+    let latest_block = from_block; // Temp fix: Todo take from block from config
+    // Nullifiers spent before this block were not seen by this run.
+    db.set_nullifiers_indexed_from(latest_block)?;
 
-    while from_block <= latest_block {
-        let to_block = (from_block + BLOCK_CHUNK_SIZE - 1).min(latest_block);
-        println!(
-            "[Indexer] Querying logs from block {} to {}",
-            from_block, to_block
-        );
+    seed_mark_price(&db, &provider, &oracle_contract, latest_block).await?;
+    let mut block_times = BlockTimestamps::default();
+    sync.start_backfill(latest_block);
+    for name in REALTIME_STREAMS {
+        sync.stream_connecting(name);
+    }
+    let latest_timestamp = block_times.timestamp(&provider, latest_block).await;
+    sync.set_head(latest_block, latest_timestamp);
+
+    let mut indexer = Indexer {
+        db,
+        provider,
+        sync: Arc::clone(&sync),
+        proxy_contract,
+        ch_contract,
+        token_pool_contract,
+        token_contract,
+        oracle_contract,
+        proxy_address,
+        tp_address,
+        token_address,
+        block_times,
+        pending_commitments: Vec::new(),
+        recent_deposits: HashSet::new(),
+        handled: HashSet::new(),
+    };
+    indexer.backfill(from_block, latest_block).await?;
+
+    let mut resume_from = latest_block + 1;
+    loop {
+        match indexer.realtime(resume_from).await {
+            Ok(ended) => stream_ended(&sync, ended),
+            Err(e) => eprintln!("[Indexer ERROR] Realtime sync failed, resubscribing: {}", e),
+        }
+        sleep(Duration::from_secs(RESUBSCRIBE_DELAY_SECS)).await;
+        // Logs after the last checkpoint may not have been delivered, so they
+        // are fetched again; `handled` skips the ones that were.
+        resume_from = sync.last_indexed_block().map_or(resume_from, |block| block + 1);
+    }
+}
+
+/// The indexer's contracts and the realtime state kept across resubscribes.
+struct Indexer {
+    db: Arc<Database>,
+    provider: Arc<Provider<Ws>>,
+    sync: Arc<SyncStatus>,
+    proxy_contract: PrivacyProxy<Provider<Ws>>,
+    ch_contract: ClearingHouseV2<Provider<Ws>>,
+    token_pool_contract: TokenPoolV2<Provider<Ws>>,
+    token_contract: Erc20<Provider<Ws>>,
+    oracle_contract: Oracle<Provider<Ws>>,
+    proxy_address: Address,
+    tp_address: Address,
+    token_address: Address,
+    block_times: BlockTimestamps,
+    // Commitments wait for the next head, by which time the deposit stream
+    // has delivered the same block's pool transfers.
+    pending_commitments: Vec<LogMeta>,
+    recent_deposits: HashSet<H256>,
+    /// Realtime logs past the last checkpoint, as `(block_number, log_index)`.
+    handled: HashSet<(U64, U256)>,
+}
+
+impl Indexer {
+    /// Fetches and applies logs for `from_block..=last_block` in chunks,
+    /// skipping any the realtime streams already handled.
+    async fn backfill(&mut self, mut from_block: u64, last_block: u64) -> Result<()> {
+        let db = Arc::clone(&self.db);
+        let provider = Arc::clone(&self.provider);
+        let Self {
+            sync,
+            proxy_contract,
+            ch_contract,
+            token_pool_contract,
+            token_contract,
+            oracle_contract,
+            proxy_address,
+            tp_address,
+            token_address,
+            block_times,
+            handled,
+            ..
+        } = self;
+        let (proxy_address, tp_address, token_address) = (*proxy_address, *tp_address, *token_address);
+
+        while from_block <= last_block {
+            let to_block = (from_block + BLOCK_CHUNK_SIZE - 1).min(last_block);
+            println!(
+                "[Indexer] Querying logs from block {} to {}",
+                from_block, to_block
+            );
+
+            let pos_open_filter = proxy_contract
+                .position_opened_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let pos_closed_filter = ch_contract
+                .position_closed_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let pos_liquidated_filter = ch_contract
+                .position_liquidated_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let note_created_filter = token_pool_contract
+                .note_created_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let note_claimed_filter = token_pool_contract
+                .note_claimed_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let commitment_filter = token_pool_contract
+                .commitment_inserted_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let pool_deposit_filter = token_contract
+                .transfer_filter()
+                .topic2(H256::from(tp_address))
+                .from_block(from_block)
+                .to_block(to_block);
+            let margin_added_filter = ch_contract
+                .margin_added_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let margin_removed_filter = ch_contract
+                .margin_removed_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let price_updated_filter = oracle_contract
+                .price_updated_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let private_deposit_filter = proxy_contract
+                .collateral_deposited_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let private_withdrawal_filter = proxy_contract
+                .collateral_withdrawn_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let public_deposit_filter = ch_contract
+                .collateral_deposited_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+            let public_withdrawal_filter = ch_contract
+                .collateral_withdrawn_filter()
+                .from_block(from_block)
+                .to_block(to_block);
+
+            let (
+                pos_opened_logs,
+                pos_closed_logs,
+                pos_liquidated_logs,
+                note_created_logs,
+                note_claimed_logs,
+                margin_added_logs,
+                margin_removed_logs,
+                price_updated_logs,
+                private_deposit_logs,
+                private_withdrawal_logs,
+                public_deposit_logs,
+                public_withdrawal_logs,
+                commitment_logs,
+                pool_deposit_logs,
+            ) = tokio::try_join!(
+                metrics::rpc("eth_getLogs", pos_open_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", pos_closed_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", pos_liquidated_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", note_created_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", note_claimed_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", margin_added_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", margin_removed_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", price_updated_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", private_deposit_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", private_withdrawal_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", public_deposit_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", public_withdrawal_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", commitment_filter.query_with_meta()),
+                metrics::rpc("eth_getLogs", pool_deposit_filter.query_with_meta())
+            )?;
+
+            let deposits: HashSet<H256> = pool_deposit_logs
+                .iter()
+                .map(|(_, meta)| meta.transaction_hash)
+                .collect();
+            // Applied in chain order, as realtime does, so e.g. a margin change
+            // lands before the close that follows it in the same chunk.
+            let mut events: Vec<(BackfillEvent, LogMeta)> = Vec::new();
+            events.extend(price_updated_logs.into_iter().map(|(log, meta)| (BackfillEvent::PriceUpdated(log), meta)));
+            events.extend(pos_opened_logs.into_iter().map(|(log, meta)| (BackfillEvent::PositionOpened(log), meta)));
+            events.extend(pos_closed_logs.into_iter().map(|(log, meta)| (BackfillEvent::PositionClosed(log), meta)));
+            events.extend(pos_liquidated_logs.into_iter().map(|(log, meta)| (BackfillEvent::PositionLiquidated(log), meta)));
+            events.extend(note_created_logs.into_iter().map(|(log, meta)| (BackfillEvent::NoteCreated(log), meta)));
+            events.extend(note_claimed_logs.into_iter().map(|(log, meta)| (BackfillEvent::NoteClaimed(log), meta)));
+            events.extend(margin_added_logs.into_iter().map(|(log, meta)| (BackfillEvent::MarginAdded(log), meta)));
+            events.extend(margin_removed_logs.into_iter().map(|(log, meta)| (BackfillEvent::MarginRemoved(log), meta)));
+            events.extend(private_deposit_logs.into_iter().map(|(log, meta)| (BackfillEvent::PrivateDeposit(log), meta)));
+            events.extend(private_withdrawal_logs.into_iter().map(|(log, meta)| (BackfillEvent::PrivateWithdrawal(log), meta)));
+            events.extend(public_deposit_logs.into_iter().map(|(log, meta)| (BackfillEvent::PublicDeposit(log), meta)));
+            events.extend(public_withdrawal_logs.into_iter().map(|(log, meta)| (BackfillEvent::PublicWithdrawal(log), meta)));
+            // Deposits insert a commitment without spending a nullifier.
+            events.extend(
+                commitment_logs
+                    .into_iter()
+                    .filter(|(_, meta)| !deposits.contains(&meta.transaction_hash))
+                    .map(|(_, meta)| (BackfillEvent::CommitmentInserted, meta)),
+            );
+            events.retain(|(_, meta)| !handled.contains(&(meta.block_number, meta.log_index)));
+            events.sort_by_key(|(_, meta)| (meta.block_number, meta.log_index));
+
+            for (event, meta) in events {
+                match event {
+                    BackfillEvent::PriceUpdated(log) => {
+                        metrics::observe("PriceUpdated", handle_price_updated(&db, log, &meta))?;
+                    }
+                    BackfillEvent::PositionOpened(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe("PrivatePositionOpened", handle_position_opened(&db, log, &ctx))?;
+                    }
+                    BackfillEvent::PositionClosed(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PositionClosed",
+                            handle_position_closed(&db, log, &ctx, proxy_address),
+                        )?;
+                    }
+                    BackfillEvent::PositionLiquidated(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PositionLiquidated",
+                            handle_position_liquidated(&db, log, &ctx, proxy_address),
+                        )?;
+                    }
+                    BackfillEvent::NoteCreated(log) => {
+                        metrics::observe(
+                            "NoteCreated",
+                            handle_note_created(&db, log, &meta, token_address).await,
+                        )?;
+                    }
+                    BackfillEvent::NoteClaimed(log) => {
+                        metrics::observe("NoteClaimed", handle_note_claimed(&db, log, &meta))?;
+                    }
+                    BackfillEvent::MarginAdded(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe("MarginAdded", handle_margin_added(&db, log, &ctx))?;
+                    }
+                    BackfillEvent::MarginRemoved(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe("MarginRemoved", handle_margin_removed(&db, log, &ctx))?;
+                    }
+                    BackfillEvent::PrivateDeposit(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PrivateCollateralDeposited",
+                            handle_private_collateral_deposited(&db, log, &ctx),
+                        )?;
+                    }
+                    BackfillEvent::PrivateWithdrawal(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PrivateCollateralWithdrawn",
+                            handle_private_collateral_withdrawn(&db, log, &ctx),
+                        )?;
+                    }
+                    BackfillEvent::PublicDeposit(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PublicCollateralDeposited",
+                            handle_public_collateral_deposited(&db, log, &ctx, proxy_address),
+                        )?;
+                    }
+                    BackfillEvent::PublicWithdrawal(log) => {
+                        let ctx = block_times.context(&provider, &meta).await;
+                        metrics::observe(
+                            "PublicCollateralWithdrawn",
+                            handle_public_collateral_withdrawn(&db, log, &ctx, proxy_address),
+                        )?;
+                    }
+                    BackfillEvent::CommitmentInserted => {
+                        metrics::observe(
+                            "CommitmentInserted",
+                            handle_commitment_inserted(&db, &provider, &meta, tp_address, proxy_address).await,
+                        )?;
+                    }
+                }
+            }
+
+            let indexed_timestamp = block_times.timestamp(&provider, to_block).await;
+            sync.advance_indexed(to_block, indexed_timestamp);
+            from_block = to_block + 1;
+            sleep(Duration::from_millis(DELAY_BETWEEN_CHUNKS_MS)).await;
+        }
+        Ok(())
+    }
+
+    /// Subscribes from the current head, catches up from `resume_from` to it
+    /// and handles events until a stream ends, returning that stream's name.
+    async fn realtime(&mut self, resume_from: u64) -> Result<&'static str> {
+        let provider = Arc::clone(&self.provider);
+        let head = metrics::rpc("eth_blockNumber", provider.get_block_number()).await?.as_u64();
+        let start_realtime_block = head + 1;
+
+        println!("[Indexer] Starting realtime sync from block {}", resume_from);
 
-        let pos_open_filter = proxy_contract
+        // Event Filters - Create filters with a longer lifetime
+        let pos_open_filter = self.proxy_contract
             .position_opened_filter()
-            .from_block(from_block)
-            .to_block(to_block);
-        let pos_closed_filter = ch_contract
+            .from_block(start_realtime_block);
+        let pos_closed_filter = self.ch_contract
             .position_closed_filter()
-            .from_block(from_block)
-            .to_block(to_block);
-        let pos_liquidated_filter = ch_contract
+            .from_block(start_realtime_block);
+        let pos_liquidated_filter = self.ch_contract
             .position_liquidated_filter()
-            .from_block(from_block)
-            .to_block(to_block);
-        let note_created_filter = token_pool_contract
+            .from_block(start_realtime_block);
+        let note_created_filter = self.token_pool_contract
             .note_created_filter()
-            .from_block(from_block)
-            .to_block(to_block);
-        let note_claimed_filter = token_pool_contract
+            .from_block(start_realtime_block);
+        let note_claimed_filter = self.token_pool_contract
             .note_claimed_filter()
-            .from_block(from_block)
-            .to_block(to_block);
-
-        let (
-            pos_opened_logs,
-            pos_closed_logs,
-            pos_liquidated_logs,
-            note_created_logs,
-            note_claimed_logs,
-        ) = tokio::try_join!(
-            pos_open_filter.query(),
-            pos_closed_filter.query(),
-            pos_liquidated_filter.query(),
-            note_created_filter.query(),
-            note_claimed_filter.query()
-        )?;
+            .from_block(start_realtime_block);
+        let commitment_filter = self.token_pool_contract
+            .commitment_inserted_filter()
+            .from_block(start_realtime_block);
+        let pool_deposit_filter = self.token_contract
+            .transfer_filter()
+            .topic2(H256::from(self.tp_address))
+            .from_block(start_realtime_block);
+        let public_pos_opened = self.ch_contract
+            .position_opened_filter()
+            .from_block(start_realtime_block);
+        let margin_added_filter = self.ch_contract
+            .margin_added_filter()
+            .from_block(start_realtime_block);
+        let margin_removed_filter = self.ch_contract
+            .margin_removed_filter()
+            .from_block(start_realtime_block);
+        let price_updated_filter = self.oracle_contract
+            .price_updated_filter()
+            .from_block(start_realtime_block);
+        let private_deposit_filter = self.proxy_contract
+            .collateral_deposited_filter()
+            .from_block(start_realtime_block);
+        let private_withdrawal_filter = self.proxy_contract
+            .collateral_withdrawn_filter()
+            .from_block(start_realtime_block);
+        let public_deposit_filter = self.ch_contract
+            .collateral_deposited_filter()
+            .from_block(start_realtime_block);
+        let public_withdrawal_filter = self.ch_contract
+            .collateral_withdrawn_filter()
+            .from_block(start_realtime_block);
 
-        for log in pos_opened_logs {
-            handle_position_opened(&db, log)?;
-        }
-        for log in pos_closed_logs {
-            handle_position_closed(&db, log)?;
+        // Event Streams - Listen from block 0 to sync history
+        let mut pos_open_stream = pos_open_filter.stream_with_meta().await?;
+        let mut pos_closed_stream = pos_closed_filter.stream_with_meta().await?;
+        let mut pos_liquidated_stream = pos_liquidated_filter.stream_with_meta().await?;
+        let mut note_created_stream = note_created_filter.stream_with_meta().await?;
+        let mut note_claimed_stream = note_claimed_filter.stream_with_meta().await?;
+        let mut commitment_stream = commitment_filter.stream_with_meta().await?;
+        let mut pool_deposit_stream = pool_deposit_filter.stream_with_meta().await?;
+        let mut public_pos_open_stream = public_pos_opened.stream_with_meta().await?;
+        let mut margin_added_stream = margin_added_filter.stream_with_meta().await?;
+        let mut margin_removed_stream = margin_removed_filter.stream_with_meta().await?;
+        let mut price_updated_stream = price_updated_filter.stream_with_meta().await?;
+        let mut private_deposit_stream = private_deposit_filter.stream_with_meta().await?;
+        let mut private_withdrawal_stream = private_withdrawal_filter.stream_with_meta().await?;
+        let mut public_deposit_stream = public_deposit_filter.stream_with_meta().await?;
+        let mut public_withdrawal_stream = public_withdrawal_filter.stream_with_meta().await?;
+        let mut head_stream = provider.subscribe_blocks().await?;
+        // The filters are installed first, so nothing mined during the catch-up is missed.
+        if resume_from <= head {
+            self.backfill(resume_from, head).await?;
         }
-        for log in pos_liquidated_logs {
-            handle_position_liquidated(&db, log)?;
-        }
-        for log in note_created_logs {
-            handle_note_created(&db, log, token_address).await?;
-        }
-        for log in note_claimed_logs {
-            handle_note_claimed(&db, log)?;
+        let db = Arc::clone(&self.db);
+        let Self {
+            sync,
+            proxy_address,
+            tp_address,
+            token_address,
+            block_times,
+            pending_commitments,
+            recent_deposits,
+            handled,
+            ..
+        } = self;
+        let (proxy_address, tp_address, token_address) = (*proxy_address, *tp_address, *token_address);
+        let mut checkpoint = tokio::time::interval(Duration::from_secs(SYNC_CHECKPOINT_INTERVAL_SECS));
+        for name in REALTIME_STREAMS {
+            sync.stream_live(name);
         }
+        sync.start_realtime();
 
-        from_block = to_block + 1;
-        sleep(Duration::from_millis(DELAY_BETWEEN_CHUNKS_MS)).await;
+        loop {
+            tokio::select! {
+                    block = head_stream.next() => {
+                        let Some(block) = block else {
+                            return Ok("NewHeads");
+                        };
+                        if let Some(number) = block.number {
+                            sync.set_head(number.as_u64(), block.timestamp.as_u64());
+                            let (ready, waiting): (Vec<_>, Vec<_>) = pending_commitments
+                                .drain(..)
+                                .partition(|meta| meta.block_number < number);
+                            *pending_commitments = waiting;
+                            for meta in ready {
+                                if recent_deposits.remove(&meta.transaction_hash) {
+                                    continue;
+                                }
+                                let _ = metrics::observe(
+                                    "CommitmentInserted",
+                                    handle_commitment_inserted(
+                                        &db,
+                                        &provider,
+                                        &meta,
+                                        tp_address,
+                                        proxy_address,
+                                    )
+                                    .await,
+                                );
+                            }
+                        }
+                    },
+                    _ = checkpoint.tick() => {
+                        sync.checkpoint();
+                        if let Some(indexed) = sync.last_indexed_block() {
+                            handled.retain(|(block, _)| block.as_u64() > indexed);
+                        }
+                        // Ages trades out of the rolling leaderboard windows even
+                        // while no new trades arrive.
+                        if let Err(e) = leaderboard::expire_trades(&db, stats::current_timestamp()) {
+                            eprintln!("[Indexer ERROR] Failed to expire leaderboard trades: {}", e);
+                        }
+                    },
+                    event = pos_open_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PrivatePositionOpened");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PrivatePositionOpened",
+                                handle_position_opened(&db, log, &ctx),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] PositionOpened stream error: {}", e);
+                            sync.stream_error("PrivatePositionOpened", &e);
+                            metrics::stream_error("PrivatePositionOpened");
+                        },
+                        None => return Ok("PrivatePositionOpened"),
+                    },
+                    event = pos_closed_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PositionClosed");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PositionClosed",
+                                handle_position_closed(&db, log, &ctx, proxy_address),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] PositionClosed stream error: {}", e);
+                            sync.stream_error("PositionClosed", &e);
+                            metrics::stream_error("PositionClosed");
+                        },
+                        None => return Ok("PositionClosed"),
+                    },
+                    event = pos_liquidated_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PositionLiquidated");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PositionLiquidated",
+                                handle_position_liquidated(&db, log, &ctx, proxy_address),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] PositionLiquidated stream error: {}", e);
+                            sync.stream_error("PositionLiquidated", &e);
+                            metrics::stream_error("PositionLiquidated");
+                        },
+                        None => return Ok("PositionLiquidated"),
+                    },
+                    event = note_created_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("NoteCreated");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let _ = metrics::observe(
+                                "NoteCreated",
+                                handle_note_created(&db, log, &meta, token_address).await,
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] NoteCreated stream error: {}", e);
+                            sync.stream_error("NoteCreated", &e);
+                            metrics::stream_error("NoteCreated");
+                        },
+                        None => return Ok("NoteCreated"),
+                    },
+                    event = note_claimed_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("NoteClaimed");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let _ = metrics::observe(
+                                "NoteClaimed",
+                                handle_note_claimed(&db, log, &meta),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] NoteClaimed stream error: {}", e);
+                            sync.stream_error("NoteClaimed", &e);
+                            metrics::stream_error("NoteClaimed");
+                        },
+                        None => return Ok("NoteClaimed"),
+                    },
+                    event = commitment_stream.next() => match event {
+                        Some(Ok((_, meta))) => {
+                            sync.stream_event("CommitmentInserted");
+                            handled.insert((meta.block_number, meta.log_index));
+                            pending_commitments.push(meta);
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] CommitmentInserted stream error: {}", e);
+                            sync.stream_error("CommitmentInserted", &e);
+                            metrics::stream_error("CommitmentInserted");
+                        },
+                        None => return Ok("CommitmentInserted"),
+                    },
+                    event = pool_deposit_stream.next() => match event {
+                        Some(Ok((_, meta))) => {
+                            sync.stream_event("PoolDeposit");
+                            handled.insert((meta.block_number, meta.log_index));
+                            // `depositFor` transfers without a commitment, so
+                            // entries can linger; bound the set.
+                            if recent_deposits.len() >= RECENT_DEPOSITS_SIZE {
+                                recent_deposits.clear();
+                            }
+                            recent_deposits.insert(meta.transaction_hash);
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] PoolDeposit stream error: {}", e);
+                            sync.stream_error("PoolDeposit", &e);
+                            metrics::stream_error("PoolDeposit");
+                        },
+                        None => return Ok("PoolDeposit"),
+                    },
+                    event = public_pos_open_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PublicPositionOpened");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PublicPositionOpened",
+                                handle_public_pos_opened(&db, log, &ctx, proxy_address),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] Public PositionOpened stream error: {}", e);
+                            sync.stream_error("PublicPositionOpened", &e);
+                            metrics::stream_error("PublicPositionOpened");
+                        },
+                        None => return Ok("PublicPositionOpened"),
+                    },
+                    event = margin_added_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("MarginAdded");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "MarginAdded",
+                                handle_margin_added(&db, log, &ctx),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] MarginAdded stream error: {}", e);
+                            sync.stream_error("MarginAdded", &e);
+                            metrics::stream_error("MarginAdded");
+                        },
+                        None => return Ok("MarginAdded"),
+                    },
+                    event = margin_removed_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("MarginRemoved");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "MarginRemoved",
+                                handle_margin_removed(&db, log, &ctx),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] MarginRemoved stream error: {}", e);
+                            sync.stream_error("MarginRemoved", &e);
+                            metrics::stream_error("MarginRemoved");
+                        },
+                        None => return Ok("MarginRemoved"),
+                    },
+                    event = price_updated_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PriceUpdated");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let _ = metrics::observe("PriceUpdated", handle_price_updated(&db, log, &meta));
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] PriceUpdated stream error: {}", e);
+                            sync.stream_error("PriceUpdated", &e);
+                            metrics::stream_error("PriceUpdated");
+                        },
+                        None => return Ok("PriceUpdated"),
+                    },
+                    event = private_deposit_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PrivateCollateralDeposited");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PrivateCollateralDeposited",
+                                handle_private_collateral_deposited(&db, log, &ctx),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] Proxy CollateralDeposited stream error: {}", e);
+                            sync.stream_error("PrivateCollateralDeposited", &e);
+                            metrics::stream_error("PrivateCollateralDeposited");
+                        },
+                        None => return Ok("PrivateCollateralDeposited"),
+                    },
+                    event = private_withdrawal_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PrivateCollateralWithdrawn");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PrivateCollateralWithdrawn",
+                                handle_private_collateral_withdrawn(&db, log, &ctx),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] Proxy CollateralWithdrawn stream error: {}", e);
+                            sync.stream_error("PrivateCollateralWithdrawn", &e);
+                            metrics::stream_error("PrivateCollateralWithdrawn");
+                        },
+                        None => return Ok("PrivateCollateralWithdrawn"),
+                    },
+                    event = public_deposit_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PublicCollateralDeposited");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PublicCollateralDeposited",
+                                handle_public_collateral_deposited(&db, log, &ctx, proxy_address),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] CollateralDeposited stream error: {}", e);
+                            sync.stream_error("PublicCollateralDeposited", &e);
+                            metrics::stream_error("PublicCollateralDeposited");
+                        },
+                        None => return Ok("PublicCollateralDeposited"),
+                    },
+                    event = public_withdrawal_stream.next() => match event {
+                        Some(Ok((log, meta))) => {
+                            sync.stream_event("PublicCollateralWithdrawn");
+                            handled.insert((meta.block_number, meta.log_index));
+                            let ctx = block_times.context(&provider, &meta).await;
+                            let _ = metrics::observe(
+                                "PublicCollateralWithdrawn",
+                                handle_public_collateral_withdrawn(&db, log, &ctx, proxy_address),
+                            );
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("[Indexer ERROR] CollateralWithdrawn stream error: {}", e);
+                            sync.stream_error("PublicCollateralWithdrawn", &e);
+                            metrics::stream_error("PublicCollateralWithdrawn");
+                        },
+                        None => return Ok("PublicCollateralWithdrawn"),
+                    }
+            };
+        }
     }
+}
 
-    let start_realtime_block = latest_block + 1;
-
-    println!("[Indexer] Starting realtime sync");
-
-    // Event Filters - Create filters with a longer lifetime
-    let pos_open_filter = proxy_contract
-        .position_opened_filter()
-        .from_block(start_realtime_block);
-    let pos_closed_filter = ch_contract
-        .position_closed_filter()
-        .from_block(start_realtime_block);
-    let pos_liquidated_filter = ch_contract
-        .position_liquidated_filter()
-        .from_block(start_realtime_block);
-    let note_created_filter = token_pool_contract
-        .note_created_filter()
-        .from_block(start_realtime_block);
-    let note_claimed_filter = token_pool_contract
-        .note_claimed_filter()
-        .from_block(start_realtime_block);
-    let public_pos_opened = ch_contract
-        .position_opened_filter()
-        .from_block(start_realtime_block);
-
-    // Event Streams - Listen from block 0 to sync history
-    let mut pos_open_stream = pos_open_filter.stream().await?;
-    let mut pos_closed_stream = pos_closed_filter.stream().await?;
-    let mut pos_liquidated_stream = pos_liquidated_filter.stream().await?;
-    let mut note_created_stream = note_created_filter.stream().await?;
-    let mut note_claimed_stream = note_claimed_filter.stream().await?;
-    let mut public_pos_open_stream = public_pos_opened.stream().await?;
+/// A subscription or log filter that ends will never deliver again; it is
+/// reported as failing until the indexer has resubscribed.
+fn stream_ended(sync: &SyncStatus, name: &'static str) {
+    eprintln!("[Indexer ERROR] {} stream ended, resubscribing", name);
+    sync.stream_error(name, &"stream ended");
+    metrics::stream_error(name);
+}
 
-    loop {
-        tokio::select! {
-                Some(event) = pos_open_stream.next() => match event {
-                    Ok(log) => { let _ = handle_position_opened(&db, log); },
-                    Err(e) => eprintln!("[Indexer ERROR] PositionOpened stream error: {}", e),
-                },
-                Some(event) = pos_closed_stream.next() => match event {
-                    Ok(log) => { let _ = handle_position_closed(&db, log); },
-                    Err(e) => eprintln!("[Indexer ERROR] PositionClosed stream error: {}", e),
-                },
-                Some(event) = pos_liquidated_stream.next() => match event {
-                    Ok(log) => { let _ = handle_position_liquidated(&db, log); },
-                    Err(e) => eprintln!("[Indexer ERROR] PositionLiquidated stream error: {}", e),
-                },
-                Some(event) = note_created_stream.next() => match event {
-                    Ok(log) => { let _ = handle_note_created(&db, log, token_address).await; },
-                    Err(e) => eprintln!("[Indexer ERROR] NoteCreated stream error: {}", e),
-                },
-                Some(event) = note_claimed_stream.next() => match event {
-                    Ok(log) => { let _ = handle_note_claimed(&db, log); },
-                    Err(e) => eprintln!("[Indexer ERROR] NoteClaimed stream error: {}", e),
-                },
-                Some(event) = public_pos_open_stream.next() => match event {
-                    Ok(log) => { let _ = handle_public_pos_opened(&db, log, proxy_address); },
-                    Err(e) => eprintln!("[Indexer ERROR] NoteClaimed stream error: {}", e),
-                }
-        };
+/// Reads the ClearingHouse constants needed for off-chain risk math.
+async fn load_protocol_constants(
+    db: &Database,
+    ch_contract: &ClearingHouseV2<Provider<Ws>>,
+) -> Result<()> {
+    let mmr_call = ch_contract.maintenance_margin_ratio_bps();
+    let precision_call = ch_contract.price_precision();
+    let taker_fee_call = ch_contract.taker_fee_bps();
+    let bps_divisor_call = ch_contract.bps_divisor();
+    let (maintenance_margin_ratio_bps, price_precision, taker_fee_bps, bps_divisor) = tokio::try_join!(
+        metrics::rpc("eth_call", mmr_call.call()),
+        metrics::rpc("eth_call", precision_call.call()),
+        metrics::rpc("eth_call", taker_fee_call.call()),
+        metrics::rpc("eth_call", bps_divisor_call.call())
+    )?;
+    let constants = ProtocolConstants {
+        maintenance_margin_ratio_bps: maintenance_margin_ratio_bps.as_u64(),
+        price_precision: price_precision.as_u64(),
+        taker_fee_bps: taker_fee_bps.as_u64(),
+        bps_divisor: bps_divisor.as_u64(),
+    };
+    println!("[Indexer] Protocol constants: {:?}", constants);
+    db.set_protocol_constants(&constants)
+}
+
+/// Seeds the mark price from the oracle so metrics are available before the
+/// first `PriceUpdated` event arrives.
+async fn seed_mark_price(
+    db: &Database,
+    provider: &Provider<Ws>,
+    oracle_contract: &Oracle<Provider<Ws>>,
+    block_number: u64,
+) -> Result<()> {
+    let price_call = oracle_contract.price().block(block_number);
+    let price = metrics::rpc("eth_call", price_call.call()).await?;
+    if price.is_zero() {
+        return Ok(());
     }
+    let timestamp = metrics::rpc("eth_getBlockByNumber", provider.get_block(block_number))
+        .await?
+        .map(|block| block.timestamp.as_u64())
+        .unwrap_or_default();
+    db.set_mark_price(&MarkPrice {
+        price: price.to_string(),
+        timestamp,
+    })
 }
 
 fn handle_public_pos_opened(
     db: &Database,
     log: clearing_house_v2::PositionOpenedFilter,
+    ctx: &EventContext,
     proxy_address: Address,
 ) -> Result<()> {
     if log.user == proxy_address {
@@ -199,19 +961,27 @@ fn handle_public_pos_opened(
     let mut owner_id = [0u8; 32];
     owner_id[12..].copy_from_slice(log.user.as_bytes());
 
-    db.add_open_position(&owner_id, position).map_err(|e| {
+    let is_new = db.add_open_position(&owner_id, position.clone()).map_err(|e| {
         eprintln!(
             "[Indexer ERROR] Failed to add public open position to DB: {}",
             e
         );
         e
     })?;
+    if is_new {
+        stats::record_position_opened(db, &position, false, ctx.timestamp)?;
+    }
+    db.record_activity(&owner_id, ctx.log_index, &opened_activity(db, ctx, &position)?)?;
 
     Ok(())
 }
 
 /// Handles a PositionOpened event.
-fn handle_position_opened(db: &Database, log: privacy_proxy::PositionOpenedFilter) -> Result<()> {
+fn handle_position_opened(
+    db: &Database,
+    log: privacy_proxy::PositionOpenedFilter,
+    ctx: &EventContext,
+) -> Result<()> {
     println!(
         "[Indexer] PositionOpened: ID 0x{}",
         hex::encode(log.position_id)
@@ -223,11 +993,16 @@ fn handle_position_opened(db: &Database, log: privacy_proxy::PositionOpenedFilte
         margin: log.margin.to_string(),
         size: log.size.to_string(),
     };
-    db.add_open_position(&log.owner_pub_key, position)
+    let is_new = db
+        .add_open_position(&log.owner_pub_key, position.clone())
         .map_err(|e: anyhow::Error| {
             eprintln!("[Indexer ERROR] Failed to add open position to DB: {}", e);
             e
         })?;
+    if is_new {
+        stats::record_position_opened(db, &position, true, ctx.timestamp)?;
+    }
+    db.record_activity(&log.owner_pub_key, ctx.log_index, &opened_activity(db, ctx, &position)?)?;
     Ok(())
 }
 
@@ -235,17 +1010,60 @@ fn handle_position_opened(db: &Database, log: privacy_proxy::PositionOpenedFilte
 fn handle_position_closed(
     db: &Database,
     log: clearing_house_v2::PositionClosedFilter,
+    ctx: &EventContext,
+    proxy_address: Address,
 ) -> Result<()> {
     println!(
         "[Indexer] PositionClosed: ID 0x{}",
         hex::encode(log.position_id)
     );
     let pnl_str = log.pnl.to_string();
-    db.move_to_historical(&log.position_id, PositionStatus::Closed, pnl_str, log.user.to_string())
+    let closed_at = ctx.timestamp;
+    let moved = db
+        .move_to_historical(
+            &log.position_id,
+            PositionStatus::Closed,
+            pnl_str,
+            log.fee.to_string(),
+            log.user.to_string(),
+            closed_at,
+        )
         .map_err(|e| {
             eprintln!("[Indexer ERROR] Failed to move position (closed): {}", e);
             e
         })?;
+    if let Some((owner, position)) = moved {
+        let is_private = log.user == proxy_address;
+        let outcome = stats::record_position_closed(
+            db,
+            &position,
+            log.pnl,
+            log.fee,
+            is_private,
+            ctx.timestamp,
+        )?;
+        db.record_activity(
+            &owner,
+            ctx.log_index,
+            &ActivityRecord {
+                position_id: Some(position.position_id.clone()),
+                is_long: Some(position.is_long),
+                size: Some(position.size.clone()),
+                entry_price: Some(position.entry_price.clone()),
+                fee: Some(log.fee.to_string()),
+                realized_pnl: Some(log.pnl.to_string()),
+                ..ctx.activity(ActivityKind::PositionClosed)
+            },
+        )?;
+        leaderboard::record_trade(
+            db,
+            &owner,
+            &position.position_id,
+            outcome,
+            is_private,
+            closed_at,
+        )?;
+    }
     Ok(())
 }
 
@@ -253,13 +1071,24 @@ fn handle_position_closed(
 fn handle_position_liquidated(
     db: &Database,
     log: clearing_house_v2::PositionLiquidatedFilter,
+    ctx: &EventContext,
+    proxy_address: Address,
 ) -> Result<()> {
     println!(
         "[Indexer] PositionLiquidated: ID 0x{}",
         hex::encode(log.position_id)
     );
     let pnl_str = "Liquidated".to_string();
-    db.move_to_historical(&log.position_id, PositionStatus::Liquidated, pnl_str, log.user.to_string())
+    let closed_at = ctx.timestamp;
+    let moved = db
+        .move_to_historical(
+            &log.position_id,
+            PositionStatus::Liquidated,
+            pnl_str,
+            log.liquidation_fee.to_string(),
+            log.user.to_string(),
+            closed_at,
+        )
         .map_err(|e| {
             eprintln!(
                 "[Indexer ERROR] Failed to move position (liquidated): {}",
@@ -267,6 +1096,47 @@ fn handle_position_liquidated(
             );
             e
         })?;
+    if let Some((owner, position)) = moved {
+        let is_private = log.user == proxy_address;
+        // The price in effect at the liquidation, not the latest one; before
+        // any update is recorded only the seeded mark price is known.
+        let mark_price = match db.price_at(ctx.timestamp, ctx.block_number, ctx.log_index)? {
+            Some(mark) => Some(mark),
+            None => db.get_mark_price()?,
+        };
+        let mark_price = mark_price
+            .map(|mark| U256::from_dec_str(&mark.price))
+            .transpose()?;
+        let outcome = stats::record_position_liquidated(
+            db,
+            &position,
+            log.liquidation_fee,
+            is_private,
+            ctx.timestamp,
+            mark_price,
+        )?;
+        db.record_activity(
+            &owner,
+            ctx.log_index,
+            &ActivityRecord {
+                position_id: Some(position.position_id.clone()),
+                is_long: Some(position.is_long),
+                size: Some(position.size.clone()),
+                entry_price: Some(position.entry_price.clone()),
+                fee: Some(log.liquidation_fee.to_string()),
+                realized_pnl: Some(outcome.realized_pnl.to_string()),
+                ..ctx.activity(ActivityKind::PositionLiquidated)
+            },
+        )?;
+        leaderboard::record_trade(
+            db,
+            &owner,
+            &position.position_id,
+            outcome,
+            is_private,
+            closed_at,
+        )?;
+    }
     Ok(())
 }
 
@@ -274,10 +1144,11 @@ fn handle_position_liquidated(
 async fn handle_note_created(
     db: &Database,
     log: token_pool_v2::NoteCreatedFilter,
+    meta: &LogMeta,
     token_address: Address,
 ) -> Result<()> {
     let mut nonce_bytes = [0u8; 32];
-    let note_nonce = U256::from(log.note_nonce);
+    let note_nonce = log.note_nonce;
     note_nonce.to_big_endian(&mut nonce_bytes);
     let address_bytes = token_address.as_bytes();
     let mut encoded_data = Vec::new();
@@ -296,21 +1167,333 @@ async fn handle_note_created(
             receiver_hash: format!("0x{}", hex::encode(log.receiver_hash)),
             value: log.amount.to_string(),
         },
+        created_block: Some(meta.block_number.as_u64()),
+        created_tx_hash: Some(format!("{:#x}", meta.transaction_hash)),
     };
     // println!("Note added {}", hex::encode(note_id));
-    db.add_unspent_note(&unspent_note).map_err(|e| {
+    let first_note = db.add_unspent_note(&unspent_note).map_err(|e| {
         eprintln!("[Indexer ERROR] Failed to add unspent note: {}", e);
         e
     })?;
+    db.record_note_event(&NoteFeedEntry {
+        kind: NoteEventKind::Created,
+        block_number: meta.block_number.as_u64(),
+        log_index: meta.log_index.as_u64(),
+        tx_hash: format!("{:#x}", meta.transaction_hash),
+        note_id: unspent_note.note_id.clone(),
+        receiver_hash: Some(unspent_note.note.receiver_hash.clone()),
+        note_nonce: Some(unspent_note.note.note_nonce),
+        amount: unspent_note.note.value.clone(),
+    })?;
+    note_digest::note_created(
+        db,
+        &log.receiver_hash,
+        first_note,
+        (meta.block_number.as_u64(), meta.log_index.as_u64()),
+    )?;
     Ok(())
 }
 
 /// Handles a NoteClaimed event.
-fn handle_note_claimed(db: &Database, log: token_pool_v2::NoteClaimedFilter) -> Result<()> {
+fn handle_note_claimed(
+    db: &Database,
+    log: token_pool_v2::NoteClaimedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
     println!("[Indexer] NoteClaimed: ID 0x{}", hex::encode(log.note_id));
-    db.remove_unspent_note(&log.note_id).map_err(|e| {
+    let claimed = db.remove_unspent_note(&log.note_id).map_err(|e| {
         eprintln!("[Indexer ERROR] Failed to remove unspent note: {}", e);
         e
     })?;
+    let mut last_note = false;
+    if let Some(note) = claimed {
+        let receiver_hash = &note.note.receiver_hash;
+        let receiver_hash = hex::decode(receiver_hash.strip_prefix("0x").unwrap_or(receiver_hash))?;
+        last_note = db.get_unspent_notes(&receiver_hash)?.is_empty();
+        db.add_spent_note(&SpentNote {
+            note,
+            claimed_block: meta.block_number.as_u64(),
+            claimed_tx_hash: format!("{:#x}", meta.transaction_hash),
+        })?;
+    }
+    db.record_note_event(&NoteFeedEntry {
+        kind: NoteEventKind::Claimed,
+        block_number: meta.block_number.as_u64(),
+        log_index: meta.log_index.as_u64(),
+        tx_hash: format!("{:#x}", meta.transaction_hash),
+        note_id: format!("0x{}", hex::encode(log.note_id)),
+        receiver_hash: None,
+        note_nonce: None,
+        amount: log.amount.to_string(),
+    })?;
+    note_digest::note_claimed(
+        db,
+        last_note,
+        (meta.block_number.as_u64(), meta.log_index.as_u64()),
+    )?;
+    Ok(())
+}
+
+/// Reads a nullifier from proof public inputs; zero means "none".
+fn nullifier_at(public_inputs: &[[u8; 32]], index: usize) -> Option<[u8; 32]> {
+    public_inputs
+        .get(index)
+        .copied()
+        .filter(|nullifier| *nullifier != [0u8; 32])
+}
+
+/// Decodes the nullifier a pool call spends, following `ProofLib`'s input layout.
+fn decode_spent_nullifier(input: &[u8], to_proxy: bool) -> Option<(NullifierSource, [u8; 32])> {
+    if to_proxy {
+        return match PrivacyProxyCalls::decode(input).ok()? {
+            PrivacyProxyCalls::DepositCollateralFromDarkPool(call) => {
+                nullifier_at(&call.params.public_inputs, 2)
+                    .map(|n| (NullifierSource::DarkPoolDeposit, n))
+            }
+            _ => None,
+        };
+    }
+    match TokenPoolV2Calls::decode(input).ok()? {
+        TokenPoolV2Calls::Withdraw(call) => {
+            nullifier_at(&call.params.public_inputs, 2).map(|n| (NullifierSource::Withdraw, n))
+        }
+        TokenPoolV2Calls::Transfer(call) => {
+            nullifier_at(&call.params.public_inputs, 2).map(|n| (NullifierSource::Transfer, n))
+        }
+        TokenPoolV2Calls::ApproveWithdrawal(call) => nullifier_at(&call.params.public_inputs, 2)
+            .map(|n| (NullifierSource::ApproveWithdrawal, n)),
+        // A first claim has no previous commitment and spends nothing.
+        TokenPoolV2Calls::Claim(call) => {
+            nullifier_at(&call.params.public_inputs, 4).map(|n| (NullifierSource::Claim, n))
+        }
+        _ => None,
+    }
+}
+
+/// Handles a CommitmentInserted event by decoding the nullifier, if any,
+/// spent by the transaction that emitted it. Callers skip deposits, which
+/// spend none. Only direct calls to the pool or the proxy can be decoded.
+async fn handle_commitment_inserted(
+    db: &Database,
+    provider: &Provider<Ws>,
+    meta: &LogMeta,
+    token_pool_address: Address,
+    proxy_address: Address,
+) -> Result<()> {
+    let Some(tx) =
+        metrics::rpc("eth_getTransactionByHash", provider.get_transaction(meta.transaction_hash))
+            .await?
+    else {
+        return Ok(());
+    };
+    let to_proxy = match tx.to {
+        Some(to) if to == proxy_address => true,
+        Some(to) if to == token_pool_address => false,
+        _ => {
+            println!(
+                "[Indexer] CommitmentInserted from indirect call in tx {:#x}, nullifier not indexed",
+                meta.transaction_hash
+            );
+            return Ok(());
+        }
+    };
+    let Some((source, nullifier)) = decode_spent_nullifier(&tx.input, to_proxy) else {
+        return Ok(());
+    };
+    println!("[Indexer] Nullifier spent via {:?}", source);
+    db.add_spent_nullifier(&SpentNullifier {
+        nullifier: format!("0x{}", hex::encode(nullifier)),
+        source,
+        block_number: meta.block_number.as_u64(),
+        tx_hash: format!("{:#x}", meta.transaction_hash),
+    })
+    .map_err(|e| {
+        eprintln!("[Indexer ERROR] Failed to record spent nullifier: {}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Handles a MarginAdded event.
+fn handle_margin_added(
+    db: &Database,
+    log: clearing_house_v2::MarginAddedFilter,
+    ctx: &EventContext,
+) -> Result<()> {
+    println!(
+        "[Indexer] MarginAdded: ID 0x{} amount {}",
+        hex::encode(log.position_id),
+        log.amount
+    );
+    let updated = db
+        .update_position_margin(&log.position_id, log.amount, true)
+        .map_err(|e| {
+            eprintln!("[Indexer ERROR] Failed to add margin: {}", e);
+            e
+        })?;
+    if updated {
+        stats::record_margin_change(db, log.amount, true)?;
+    }
+    record_margin_activity(db, &log.position_id, log.amount, ActivityKind::MarginAdded, ctx)?;
+    Ok(())
+}
+
+/// Handles a MarginRemoved event.
+fn handle_margin_removed(
+    db: &Database,
+    log: clearing_house_v2::MarginRemovedFilter,
+    ctx: &EventContext,
+) -> Result<()> {
+    println!(
+        "[Indexer] MarginRemoved: ID 0x{} amount {}",
+        hex::encode(log.position_id),
+        log.amount
+    );
+    let updated = db
+        .update_position_margin(&log.position_id, log.amount, false)
+        .map_err(|e| {
+            eprintln!("[Indexer ERROR] Failed to remove margin: {}", e);
+            e
+        })?;
+    if updated {
+        stats::record_margin_change(db, log.amount, false)?;
+    }
+    record_margin_activity(db, &log.position_id, log.amount, ActivityKind::MarginRemoved, ctx)?;
+    Ok(())
+}
+
+/// Handles an Oracle PriceUpdated event.
+fn handle_price_updated(
+    db: &Database,
+    log: oracle::PriceUpdatedFilter,
+    meta: &LogMeta,
+) -> Result<()> {
+    println!("[Indexer] PriceUpdated: {}", log.new_price);
+    db.record_price_update(
+        &MarkPrice {
+            price: log.new_price.to_string(),
+            timestamp: log.timestamp.as_u64(),
+        },
+        meta.block_number.as_u64(),
+        meta.log_index.as_u64(),
+    )
+    .map_err(|e| {
+        eprintln!("[Indexer ERROR] Failed to record price update: {}", e);
+        e
+    })?;
     Ok(())
 }
+
+fn opened_activity(db: &Database, ctx: &EventContext, position: &Position) -> Result<ActivityRecord> {
+    Ok(ActivityRecord {
+        position_id: Some(position.position_id.clone()),
+        is_long: Some(position.is_long),
+        size: Some(position.size.clone()),
+        entry_price: Some(position.entry_price.clone()),
+        amount: Some(position.margin.clone()),
+        fee: stats::opening_fee(db, position)?.map(|fee| fee.to_string()),
+        ..ctx.activity(ActivityKind::PositionOpened)
+    })
+}
+
+fn record_margin_activity(
+    db: &Database,
+    position_id: &[u8; 32],
+    amount: U256,
+    kind: ActivityKind,
+    ctx: &EventContext,
+) -> Result<()> {
+    let Some(owner) = db.get_position_owner(position_id)? else {
+        return Ok(());
+    };
+    db.record_activity(
+        &owner,
+        ctx.log_index,
+        &ActivityRecord {
+            position_id: Some(format!("0x{}", hex::encode(position_id))),
+            amount: Some(amount.to_string()),
+            ..ctx.activity(kind)
+        },
+    )
+}
+
+fn record_collateral_activity(
+    db: &Database,
+    owner: &[u8],
+    amount: U256,
+    kind: ActivityKind,
+    ctx: &EventContext,
+) -> Result<()> {
+    db.record_activity(
+        owner,
+        ctx.log_index,
+        &ActivityRecord {
+            amount: Some(amount.to_string()),
+            ..ctx.activity(kind)
+        },
+    )
+    .map_err(|e| {
+        eprintln!("[Indexer ERROR] Failed to record collateral activity: {}", e);
+        e
+    })
+}
+
+/// Handles a PrivacyProxy CollateralDeposited event.
+fn handle_private_collateral_deposited(
+    db: &Database,
+    log: privacy_proxy::CollateralDepositedFilter,
+    ctx: &EventContext,
+) -> Result<()> {
+    record_collateral_activity(
+        db,
+        &log.owner_pub_key,
+        log.amount,
+        ActivityKind::CollateralDeposited,
+        ctx,
+    )
+}
+
+/// Handles a PrivacyProxy CollateralWithdrawn event.
+fn handle_private_collateral_withdrawn(
+    db: &Database,
+    log: privacy_proxy::CollateralWithdrawnFilter,
+    ctx: &EventContext,
+) -> Result<()> {
+    record_collateral_activity(
+        db,
+        &log.owner_pub_key,
+        log.amount,
+        ActivityKind::CollateralWithdrawn,
+        ctx,
+    )
+}
+
+/// Handles a ClearingHouse CollateralDeposited event from a public trader.
+fn handle_public_collateral_deposited(
+    db: &Database,
+    log: clearing_house_v2::CollateralDepositedFilter,
+    ctx: &EventContext,
+    proxy_address: Address,
+) -> Result<()> {
+    if log.user == proxy_address {
+        return Ok(());
+    }
+    let mut owner_id = [0u8; 32];
+    owner_id[12..].copy_from_slice(log.user.as_bytes());
+    record_collateral_activity(db, &owner_id, log.amount, ActivityKind::CollateralDeposited, ctx)
+}
+
+/// Handles a ClearingHouse CollateralWithdrawn event from a public trader.
+fn handle_public_collateral_withdrawn(
+    db: &Database,
+    log: clearing_house_v2::CollateralWithdrawnFilter,
+    ctx: &EventContext,
+    proxy_address: Address,
+) -> Result<()> {
+    if log.user == proxy_address {
+        return Ok(());
+    }
+    let mut owner_id = [0u8; 32];
+    owner_id[12..].copy_from_slice(log.user.as_bytes());
+    record_collateral_activity(db, &owner_id, log.amount, ActivityKind::CollateralWithdrawn, ctx)
+}
@@ -0,0 +1,387 @@
+// src/leaderboard.rs
+//! Incrementally maintained trader rankings.
+//!
+//! Every realized trade is added to each window's per-trader totals once and,
+//! for the rolling windows, subtracted once when it ages out. A trade's record
+//! is kept only until it has aged out of every rolling window. Rankings live
+//! in `leaderboard_rank` under order-preserving keys, so a page is a prefix
+//! scan.
+//!
+//! Only the indexer writes here (trades, expiry) plus the opt-in endpoints;
+//! every writer holds `WRITE_LOCK`, so a trade is never added or expired
+//! twice by concurrent read-modify-write cycles. Reads take no lock.
+use crate::{
+    database::Database,
+    models::{
+        LeaderboardEntry, LeaderboardMetric, LeaderboardWindow, TradeOutcome, TraderStats,
+    },
+    stats,
+};
+use anyhow::Result;
+use ethers::types::{I256, U256};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, MutexGuard};
+
+const EXPIRY_CURSOR_PREFIX: &str = "expired_until:";
+
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+fn write_lock() -> MutexGuard<'static, ()> {
+    WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Serialize, Deserialize)]
+struct TradeRecord {
+    owner: Vec<u8>,
+    is_private: bool,
+    outcome: TradeOutcome,
+}
+
+fn stats_key(window: LeaderboardWindow, owner: &[u8]) -> Vec<u8> {
+    let mut key = format!("{}:", window.label()).into_bytes();
+    key.extend_from_slice(owner);
+    key
+}
+
+fn rank_prefix(window: LeaderboardWindow, metric: LeaderboardMetric) -> Vec<u8> {
+    format!("{}:{}:", window.label(), metric.label()).into_bytes()
+}
+
+fn roi_bps(stats: &TraderStats) -> I256 {
+    if stats.margin.is_zero() {
+        return I256::zero();
+    }
+    stats.realized_pnl * I256::from(10_000) / I256::from_raw(stats.margin)
+}
+
+fn win_rate_bps(stats: &TraderStats) -> U256 {
+    if stats.trades == 0 {
+        return U256::zero();
+    }
+    U256::from(stats.wins) * U256::from(10_000) / U256::from(stats.trades)
+}
+
+/// Big-endian encoding that sorts signed values correctly as bytes.
+fn signed_sort_key(value: I256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.into_raw().to_big_endian(&mut bytes);
+    bytes[0] ^= 0x80;
+    bytes
+}
+
+fn unsigned_sort_key(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn rank_key(
+    window: LeaderboardWindow,
+    metric: LeaderboardMetric,
+    stats: &TraderStats,
+    owner: &[u8],
+) -> Vec<u8> {
+    let score = match metric {
+        LeaderboardMetric::Pnl => signed_sort_key(stats.realized_pnl),
+        LeaderboardMetric::Roi => signed_sort_key(roi_bps(stats)),
+        LeaderboardMetric::WinRate => unsigned_sort_key(win_rate_bps(stats)),
+        LeaderboardMetric::Volume => unsigned_sort_key(stats.volume),
+    };
+    let mut key = rank_prefix(window, metric);
+    key.extend_from_slice(&score);
+    key.extend_from_slice(owner);
+    key
+}
+
+/// Private-proxy accounts are only ranked once their owner has opted in.
+fn is_visible(db: &Database, owner: &[u8], stats: &TraderStats) -> Result<bool> {
+    Ok(!stats.is_private || db.leaderboard_opt_in.contains_key(owner)?)
+}
+
+fn get_stats(db: &Database, window: LeaderboardWindow, owner: &[u8]) -> Result<Option<TraderStats>> {
+    match db.leaderboard_stats.get(stats_key(window, owner))? {
+        Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        None => Ok(None),
+    }
+}
+
+fn remove_rank_entries(
+    db: &Database,
+    window: LeaderboardWindow,
+    owner: &[u8],
+    stats: &TraderStats,
+) -> Result<()> {
+    for metric in LeaderboardMetric::ALL {
+        db.leaderboard_rank
+            .remove(rank_key(window, metric, stats, owner))?;
+    }
+    Ok(())
+}
+
+fn insert_rank_entries(
+    db: &Database,
+    window: LeaderboardWindow,
+    owner: &[u8],
+    stats: &TraderStats,
+) -> Result<()> {
+    if stats.trades == 0 || !is_visible(db, owner, stats)? {
+        return Ok(());
+    }
+    for metric in LeaderboardMetric::ALL {
+        db.leaderboard_rank
+            .insert(rank_key(window, metric, stats, owner), &[])?;
+    }
+    Ok(())
+}
+
+/// Applies `update` to an owner's stats for one window and moves their rank entries.
+fn update_stats(
+    db: &Database,
+    window: LeaderboardWindow,
+    owner: &[u8],
+    update: impl FnOnce(&mut TraderStats),
+) -> Result<()> {
+    let previous = get_stats(db, window, owner)?;
+    if let Some(previous) = &previous {
+        remove_rank_entries(db, window, owner, previous)?;
+    }
+    let mut stats = previous.unwrap_or_default();
+    update(&mut stats);
+
+    if stats.trades == 0 {
+        db.leaderboard_stats.remove(stats_key(window, owner))?;
+        return Ok(());
+    }
+    db.leaderboard_stats
+        .insert(stats_key(window, owner), serde_json::to_vec(&stats)?)?;
+    insert_rank_entries(db, window, owner, &stats)
+}
+
+fn apply(stats: &mut TraderStats, outcome: &TradeOutcome, is_private: bool) {
+    stats.is_private = is_private;
+    stats.realized_pnl += outcome.realized_pnl;
+    stats.margin += outcome.margin;
+    stats.volume += outcome.volume;
+    stats.trades += 1;
+    if outcome.realized_pnl > I256::zero() {
+        stats.wins += 1;
+    }
+}
+
+fn revert(stats: &mut TraderStats, outcome: &TradeOutcome) {
+    stats.realized_pnl -= outcome.realized_pnl;
+    stats.margin = stats.margin.saturating_sub(outcome.margin);
+    stats.volume = stats.volume.saturating_sub(outcome.volume);
+    stats.trades = stats.trades.saturating_sub(1);
+    if outcome.realized_pnl > I256::zero() {
+        stats.wins = stats.wins.saturating_sub(1);
+    }
+}
+
+/// Adds a realized trade to every window.
+pub fn record_trade(
+    db: &Database,
+    owner: &[u8],
+    position_id: &str,
+    outcome: TradeOutcome,
+    is_private: bool,
+    timestamp: u64,
+) -> Result<()> {
+    let _guard = write_lock();
+    expire(db, stats::current_timestamp())?;
+    for window in LeaderboardWindow::ALL {
+        // A trade replayed from older blocks may already be outside the window.
+        if timestamp < expired_until(db, window)? {
+            continue;
+        }
+        update_stats(db, window, owner, |stats| apply(stats, &outcome, is_private))?;
+    }
+
+    // Only rolling windows read the record back, to expire the trade.
+    if timestamp < retained_from(db)? {
+        return Ok(());
+    }
+    let mut key = timestamp.to_be_bytes().to_vec();
+    key.extend_from_slice(position_id.as_bytes());
+    let record = TradeRecord {
+        owner: owner.to_vec(),
+        is_private,
+        outcome,
+    };
+    db.leaderboard_trades.insert(key, serde_json::to_vec(&record)?)?;
+    Ok(())
+}
+
+fn expiry_cursor_key(window: LeaderboardWindow) -> String {
+    format!("{}{}", EXPIRY_CURSOR_PREFIX, window.label())
+}
+
+/// Trades closed before this timestamp have been removed from `window`.
+fn expired_until(db: &Database, window: LeaderboardWindow) -> Result<u64> {
+    match db.protocol_state.get(expiry_cursor_key(window))? {
+        Some(data) => Ok(u64::from_be_bytes(data.as_ref().try_into()?)),
+        None => Ok(0),
+    }
+}
+
+/// Trades closed before this timestamp have aged out of every rolling window.
+fn retained_from(db: &Database) -> Result<u64> {
+    let mut retained_from = u64::MAX;
+    for window in LeaderboardWindow::ALL {
+        if window.seconds().is_some() {
+            retained_from = retained_from.min(expired_until(db, window)?);
+        }
+    }
+    Ok(retained_from)
+}
+
+/// Subtracts trades that have aged out of each rolling window since the last call.
+pub fn expire_trades(db: &Database, now: u64) -> Result<()> {
+    let _guard = write_lock();
+    expire(db, now)
+}
+
+fn expire(db: &Database, now: u64) -> Result<()> {
+    for window in LeaderboardWindow::ALL {
+        let Some(length) = window.seconds() else {
+            continue;
+        };
+        let expired_until = expired_until(db, window)?;
+        let cutoff = now.saturating_sub(length);
+        if cutoff <= expired_until {
+            continue;
+        }
+
+        for item in db
+            .leaderboard_trades
+            .range(expired_until.to_be_bytes()..cutoff.to_be_bytes())
+        {
+            let (_, value) = item?;
+            let record: TradeRecord = serde_json::from_slice(&value)?;
+            update_stats(db, window, &record.owner, |stats| revert(stats, &record.outcome))?;
+        }
+        db.protocol_state
+            .insert(expiry_cursor_key(window), &cutoff.to_be_bytes())?;
+    }
+
+    let retained_from = retained_from(db)?;
+    for item in db.leaderboard_trades.range(..retained_from.to_be_bytes()) {
+        let (key, _) = item?;
+        db.leaderboard_trades.remove(key)?;
+    }
+    Ok(())
+}
+
+/// Shows or hides a private owner's existing rank entries.
+pub fn set_opt_in(db: &Database, owner: &[u8], opt_in: bool) -> Result<()> {
+    let _guard = write_lock();
+    for window in LeaderboardWindow::ALL {
+        if let Some(stats) = get_stats(db, window, owner)? {
+            remove_rank_entries(db, window, owner, &stats)?;
+        }
+    }
+    if opt_in {
+        db.leaderboard_opt_in.insert(owner, &[1u8])?;
+    } else {
+        db.leaderboard_opt_in.remove(owner)?;
+    }
+    for window in LeaderboardWindow::ALL {
+        if let Some(stats) = get_stats(db, window, owner)? {
+            insert_rank_entries(db, window, owner, &stats)?;
+        }
+    }
+    Ok(())
+}
+
+fn display_owner(owner: &[u8], is_private: bool) -> String {
+    if !is_private && owner.len() == 32 {
+        // Public traders are keyed by their address left-padded to bytes32.
+        format!("0x{}", hex::encode(&owner[12..]))
+    } else {
+        format!("0x{}", hex::encode(owner))
+    }
+}
+
+pub fn get_leaderboard(
+    db: &Database,
+    window: LeaderboardWindow,
+    metric: LeaderboardMetric,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<LeaderboardEntry>> {
+    let prefix_len = rank_prefix(window, metric).len();
+    let mut entries = Vec::with_capacity(limit);
+    for (index, item) in db
+        .leaderboard_rank
+        .scan_prefix(rank_prefix(window, metric))
+        .rev()
+        .skip(offset)
+        .take(limit)
+        .enumerate()
+    {
+        let (key, _) = item?;
+        let owner = &key[prefix_len + 32..];
+        let Some(stats) = get_stats(db, window, owner)? else {
+            continue;
+        };
+        entries.push(LeaderboardEntry {
+            rank: offset + index + 1,
+            trader: display_owner(owner, stats.is_private),
+            is_private: stats.is_private,
+            realized_pnl: stats.realized_pnl.to_string(),
+            roi_bps: roi_bps(&stats).to_string(),
+            win_rate_bps: win_rate_bps(&stats).to_string(),
+            volume: stats.volume.to_string(),
+            trades: stats.trades,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary() -> Database {
+        Database::from_db(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    fn outcome(pnl: i64) -> TradeOutcome {
+        TradeOutcome {
+            realized_pnl: I256::from(pnl),
+            margin: U256::from(1_000),
+            volume: U256::from(10_000),
+        }
+    }
+
+    fn trades(db: &Database, window: LeaderboardWindow) -> u64 {
+        get_stats(db, window, b"owner").unwrap().map_or(0, |stats| stats.trades)
+    }
+
+    #[test]
+    fn trade_records_are_dropped_once_out_of_every_window() {
+        let db = temporary();
+        let now = stats::current_timestamp();
+        record_trade(&db, b"owner", "0x01", outcome(5), false, now).unwrap();
+        assert_eq!(db.leaderboard_trades.len(), 1);
+
+        expire_trades(&db, now + 2 * 86_400).unwrap();
+        assert_eq!(trades(&db, LeaderboardWindow::Day), 0);
+        assert_eq!(trades(&db, LeaderboardWindow::Week), 1);
+        assert_eq!(db.leaderboard_trades.len(), 1);
+
+        expire_trades(&db, now + 8 * 86_400).unwrap();
+        assert_eq!(trades(&db, LeaderboardWindow::Week), 0);
+        assert_eq!(trades(&db, LeaderboardWindow::AllTime), 1);
+        assert!(db.leaderboard_trades.is_empty());
+    }
+
+    #[test]
+    fn replayed_trade_outside_every_window_keeps_no_record() {
+        let db = temporary();
+        let closed_at = stats::current_timestamp() - 8 * 86_400;
+        record_trade(&db, b"owner", "0x01", outcome(5), false, closed_at).unwrap();
+        assert_eq!(trades(&db, LeaderboardWindow::AllTime), 1);
+        assert!(db.leaderboard_trades.is_empty());
+    }
+}
@@ -1,13 +1,23 @@
 mod api;
 mod config;
 mod database;
+mod export;
 mod indexer;
+mod leaderboard;
+mod metrics;
 mod models;
+mod note_digest;
+mod rate_limit;
+mod risk;
+mod stats;
+mod status;
+mod tls;
 
 use anyhow::Result;
 use config::Config;
 use database::Database;
 use ethers::providers::{Middleware, Provider, Ws};
+use status::SyncStatus;
 use std::sync::Arc;
 
 #[tokio::main]
@@ -40,11 +50,17 @@ async fn main() -> Result<()> {
     // 4. Start the two main services concurrently
     println!("🚀 Starting API Server and Blockchain Indexer...");
 
-    let api_handle = tokio::spawn(api::run_api_server(Arc::clone(&config), Arc::clone(&db)));
+    let sync_status = Arc::new(SyncStatus::default());
+    let api_handle = tokio::spawn(api::run_api_server(
+        Arc::clone(&config),
+        Arc::clone(&db),
+        Arc::clone(&sync_status),
+    ));
     let indexer_handle = tokio::spawn(indexer::run_indexer(
         Arc::clone(&config),
         Arc::clone(&db),
         Arc::clone(&provider),
+        Arc::clone(&sync_status),
     ));
 
     // Keep the application running and handle exits gracefully
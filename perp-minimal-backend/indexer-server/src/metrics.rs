@@ -0,0 +1,216 @@
+// src/metrics.rs
+//! Prometheus metrics for the indexer and API, served at `/metrics`.
+use crate::{database::Database, status::SyncStatus};
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use std::{
+    future::Future,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+/// `Tree::len` walks the whole tree, so sizes are refreshed in the background
+/// this often and scrapes read the last values.
+pub const TREE_SIZE_REFRESH: Duration = Duration::from_secs(60);
+
+static EVENTS_PROCESSED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "indexer_events_processed_total",
+        "Contract events handled, by event type",
+        &["event"]
+    )
+    .expect("metric can be registered")
+});
+
+static HANDLER_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "indexer_handler_failures_total",
+        "Event handlers that returned an error, by event type",
+        &["event"]
+    )
+    .expect("metric can be registered")
+});
+
+static STREAM_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "indexer_stream_errors_total",
+        "Errors reported by realtime event streams, by event type",
+        &["event"]
+    )
+    .expect("metric can be registered")
+});
+
+static LAG_BLOCKS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("indexer_lag_blocks", "Chain head minus last indexed block")
+        .expect("metric can be registered")
+});
+
+static LAG_SECONDS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "indexer_lag_seconds",
+        "Head block timestamp minus last indexed block timestamp"
+    )
+    .expect("metric can be registered")
+});
+
+static LAST_INDEXED_BLOCK: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("indexer_last_indexed_block", "Last fully indexed block")
+        .expect("metric can be registered")
+});
+
+static RPC_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "indexer_rpc_duration_seconds",
+        "Latency of RPC calls made by the indexer, by method",
+        &["method"]
+    )
+    .expect("metric can be registered")
+});
+
+static RPC_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "indexer_rpc_errors_total",
+        "Failed RPC calls made by the indexer, by method",
+        &["method"]
+    )
+    .expect("metric can be registered")
+});
+
+static TREE_ENTRIES: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!("sled_tree_entries", "Number of entries per sled tree", &["tree"])
+        .expect("metric can be registered")
+});
+
+static DB_SIZE_BYTES: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("sled_size_on_disk_bytes", "Size of the sled database on disk")
+        .expect("metric can be registered")
+});
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "api_requests_total",
+        "API requests, by route, method and status",
+        &["route", "method", "status"]
+    )
+    .expect("metric can be registered")
+});
+
+static HTTP_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "api_request_duration_seconds",
+        "API request latency, by route, method and status",
+        &["route", "method", "status"]
+    )
+    .expect("metric can be registered")
+});
+
+pub fn event_processed(event: &str) {
+    EVENTS_PROCESSED.with_label_values(&[event]).inc();
+}
+
+pub fn handler_failed(event: &str) {
+    HANDLER_FAILURES.with_label_values(&[event]).inc();
+}
+
+pub fn stream_error(event: &str) {
+    STREAM_ERRORS.with_label_values(&[event]).inc();
+}
+
+/// Counts a handled event and, if its handler failed, the failure.
+pub fn observe<T, E>(event: &str, result: Result<T, E>) -> Result<T, E> {
+    event_processed(event);
+    if result.is_err() {
+        handler_failed(event);
+    }
+    result
+}
+
+/// Times an RPC call and counts it as failed if it returns an error.
+pub async fn rpc<T, E>(method: &str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let started = Instant::now();
+    let result = call.await;
+    RPC_LATENCY
+        .with_label_values(&[method])
+        .observe(started.elapsed().as_secs_f64());
+    if result.is_err() {
+        RPC_ERRORS.with_label_values(&[method]).inc();
+    }
+    result
+}
+
+fn refresh_db_metrics(db: &Database) {
+    match db.tree_sizes() {
+        Ok(sizes) => {
+            for (tree, entries) in sizes {
+                TREE_ENTRIES
+                    .with_label_values(&[tree.as_str()])
+                    .set(entries as i64);
+            }
+        }
+        Err(e) => eprintln!("[API ERROR] Failed to read sled tree sizes: {}", e),
+    }
+    if let Ok(size) = db.size_on_disk() {
+        DB_SIZE_BYTES.set(size as i64);
+    }
+}
+
+/// Updates the database gauges every `TREE_SIZE_REFRESH`. Counting walks
+/// every row, so it runs on the blocking pool.
+pub async fn refresh_db_metrics_periodically(db: Arc<Database>) {
+    let mut interval = tokio::time::interval(TREE_SIZE_REFRESH);
+    loop {
+        interval.tick().await;
+        let db = Arc::clone(&db);
+        if let Err(e) = tokio::task::spawn_blocking(move || refresh_db_metrics(&db)).await {
+            eprintln!("[API ERROR] Database metrics refresh panicked: {}", e);
+        }
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn render(sync: &SyncStatus) -> String {
+    let snapshot = sync.snapshot();
+    if let Some(blocks) = snapshot.lag_blocks {
+        LAG_BLOCKS.set(blocks as i64);
+    }
+    if let Some(seconds) = snapshot.lag_seconds {
+        LAG_SECONDS.set(seconds as i64);
+    }
+    if let Some(block) = snapshot.last_indexed_block {
+        LAST_INDEXED_BLOCK.set(block as i64);
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        eprintln!("[API ERROR] Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Records request counts and latency labelled by the matched route
+/// template, so path parameters do not explode label cardinality.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+    let labels = [route.as_str(), method.as_str(), status.as_str()];
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_LATENCY
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
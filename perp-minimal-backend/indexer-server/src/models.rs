@@ -1,5 +1,6 @@
 // --- Position Models ---
 
+use ethers::types::{I256, U256};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -24,7 +25,308 @@ pub struct HistoricalPosition {
     pub position: Position,
     pub status: PositionStatus,
     pub final_pnl: String, // i256 as string
+    #[serde(default)]
+    pub fee: String, // closing or liquidation fee, empty for records indexed before it was tracked
     pub owner_address: String,
+    #[serde(default)]
+    pub closed_at: u64, // unix seconds, 0 for records indexed before it was tracked
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySort {
+    ClosedAt,
+    Pnl,
+    Size,
+}
+
+/// Filters for `Database::query_historical_positions`.
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    pub status: Option<PositionStatus>,
+    pub is_long: Option<bool>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub min_size: Option<U256>,
+    pub sort: HistorySort,
+    pub descending: bool,
+    /// Sort key of the last position on the previous page.
+    pub after: Option<Vec<u8>>,
+}
+
+/// Value stored in the `history_index` tree, enough to filter without
+/// loading the full record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryIndexEntry {
+    pub position_id: String,
+    pub closed_at: u64,
+    pub size: String,
+    pub realized_pnl: String,
+}
+
+/// An open position enriched with risk figures computed against the latest
+/// oracle price. `metrics` is `None` until the indexer has seen a price.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenPositionView {
+    #[serde(flatten)]
+    pub position: Position,
+    pub metrics: Option<PositionMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionMetrics {
+    pub mark_price: String,
+    pub notional_value: String,
+    pub unrealized_pnl: String, // i256 as string, same formula as ClearingHouse.calculatePnl
+    pub estimated_close_fee: String,
+    pub equity: String, // margin + unrealized_pnl
+    pub leverage_bps: Option<String>, // notional / equity, in basis points
+    pub margin_ratio_bps: Option<String>, // equity / notional, in basis points
+    pub maintenance_margin: String,
+    pub liquidation_price: Option<String>, // None if the position cannot be liquidated
+    pub is_solvent: bool,
+}
+
+// --- Account Activity Models ---
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub enum ActivityKind {
+    PositionOpened,
+    PositionClosed,
+    PositionLiquidated,
+    MarginAdded,
+    MarginRemoved,
+    CollateralDeposited,
+    CollateralWithdrawn,
+}
+
+/// One ledger line of an owner's account statement. Amounts are raw
+/// 18-decimal integers as strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub kind: ActivityKind,
+    pub timestamp: u64,
+    pub block_number: u64,
+    pub tx_hash: String,
+    pub position_id: Option<String>,
+    pub is_long: Option<bool>,
+    pub size: Option<String>,
+    pub entry_price: Option<String>,
+    pub amount: Option<String>, // margin or collateral moved
+    pub fee: Option<String>,
+    pub realized_pnl: Option<String>, // i256 as string
+}
+
+// --- Protocol Models ---
+
+/// Constants read once from the ClearingHouse at indexer startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolConstants {
+    pub maintenance_margin_ratio_bps: u64,
+    pub price_precision: u64,
+    pub taker_fee_bps: u64,
+    pub bps_divisor: u64,
+}
+
+/// The most recent `Oracle.PriceUpdated` seen by the indexer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkPrice {
+    pub price: String,
+    pub timestamp: u64,
+}
+
+/// Running protocol-wide aggregates maintained by the indexer.
+/// Sizes are in base-asset units, all other amounts in collateral units.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProtocolTotals {
+    pub long_open_interest: U256,
+    pub short_open_interest: U256,
+    pub open_position_count: u64,
+    pub open_margin: U256,
+    pub cumulative_volume: U256,
+    pub taker_fees: U256,
+    pub liquidation_count: u64,
+    pub liquidation_volume: U256,
+    pub liquidation_fees: U256,
+    pub margin_added: U256,
+    pub margin_removed: U256,
+    pub private_positions_opened: u64,
+    pub public_positions_opened: u64,
+    pub private_volume: U256,
+    pub public_volume: U256,
+}
+
+// --- Leaderboard Models ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderboardWindow {
+    Day,
+    Week,
+    AllTime,
+}
+
+impl LeaderboardWindow {
+    pub const ALL: [LeaderboardWindow; 3] = [
+        LeaderboardWindow::Day,
+        LeaderboardWindow::Week,
+        LeaderboardWindow::AllTime,
+    ];
+
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "24h" => Some(LeaderboardWindow::Day),
+            "7d" => Some(LeaderboardWindow::Week),
+            "all" => Some(LeaderboardWindow::AllTime),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LeaderboardWindow::Day => "24h",
+            LeaderboardWindow::Week => "7d",
+            LeaderboardWindow::AllTime => "all",
+        }
+    }
+
+    /// Window length in seconds, `None` for all time.
+    pub fn seconds(&self) -> Option<u64> {
+        match self {
+            LeaderboardWindow::Day => Some(86_400),
+            LeaderboardWindow::Week => Some(7 * 86_400),
+            LeaderboardWindow::AllTime => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderboardMetric {
+    Pnl,
+    Roi,
+    WinRate,
+    Volume,
+}
+
+impl LeaderboardMetric {
+    pub const ALL: [LeaderboardMetric; 4] = [
+        LeaderboardMetric::Pnl,
+        LeaderboardMetric::Roi,
+        LeaderboardMetric::WinRate,
+        LeaderboardMetric::Volume,
+    ];
+
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "pnl" => Some(LeaderboardMetric::Pnl),
+            "roi" => Some(LeaderboardMetric::Roi),
+            "win_rate" => Some(LeaderboardMetric::WinRate),
+            "volume" => Some(LeaderboardMetric::Volume),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LeaderboardMetric::Pnl => "pnl",
+            LeaderboardMetric::Roi => "roi",
+            LeaderboardMetric::WinRate => "win_rate",
+            LeaderboardMetric::Volume => "volume",
+        }
+    }
+}
+
+/// Realized result of a single closed or liquidated position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeOutcome {
+    pub realized_pnl: I256, // net of the closing fee
+    pub margin: U256,
+    pub volume: U256, // entry + exit notional
+}
+
+/// A trader's aggregate over one leaderboard window.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraderStats {
+    pub is_private: bool,
+    pub realized_pnl: I256,
+    pub margin: U256,
+    pub volume: U256,
+    pub trades: u64,
+    pub wins: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub trader: String,
+    pub is_private: bool,
+    pub realized_pnl: String,
+    pub roi_bps: String,
+    pub win_rate_bps: String,
+    pub volume: String,
+    pub trades: u64,
+}
+
+// --- Price Models ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandleInterval {
+    OneMinute,
+    FiveMinutes,
+    OneHour,
+    OneDay,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 4] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::OneDay,
+    ];
+
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "1m" => Some(CandleInterval::OneMinute),
+            "5m" => Some(CandleInterval::FiveMinutes),
+            "1h" => Some(CandleInterval::OneHour),
+            "1d" => Some(CandleInterval::OneDay),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::OneDay => "1d",
+        }
+    }
+
+    pub fn seconds(&self) -> u64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 300,
+            CandleInterval::OneHour => 3_600,
+            CandleInterval::OneDay => 86_400,
+        }
+    }
+
+    pub fn bucket_start(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub open_time: u64,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub first_update: u64,
+    pub last_update: u64,
+    pub num_updates: u64,
 }
 
 // --- Note Models ---
@@ -41,10 +343,87 @@ pub struct UnspentNote {
     pub note_id: String,
     #[serde(flatten)]
     pub note: Note,
+    // Absent for notes indexed before creation details were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_tx_hash: Option<String>,
+}
+
+/// A note that has been claimed, kept for wallet history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpentNote {
+    #[serde(flatten)]
+    pub note: UnspentNote,
+    pub claimed_block: u64,
+    pub claimed_tx_hash: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub enum NoteStatus {
+    Unspent,
+    Claimed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteHistoryEntry {
+    #[serde(flatten)]
+    pub note: UnspentNote,
+    pub status: NoteStatus,
+    pub claimed_block: Option<u64>,
+    pub claimed_tx_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub enum NoteEventKind {
+    Created,
+    Claimed,
+}
+
+/// One entry of the public note feed wallets sync to scan locally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteFeedEntry {
+    pub kind: NoteEventKind,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub tx_hash: String,
+    pub note_id: String,
+    pub receiver_hash: Option<String>, // only known for `Created`
+    pub note_nonce: Option<u64>,
+    pub amount: String,
+}
+
+/// The pool function whose input carried a spent nullifier.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub enum NullifierSource {
+    Withdraw,
+    Transfer,
+    Claim,
+    ApproveWithdrawal,
+    DarkPoolDeposit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpentNullifier {
+    pub nullifier: String,
+    pub source: NullifierSource,
+    pub block_number: u64,
+    pub tx_hash: String,
 }
 
 // --- Metadata Model ---
 
+/// One version of an encrypted metadata slot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataBlob {
+    pub version: u64,
+    pub updated_at: u64,
+    pub data: String, // hex-encoded ciphertext
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMetadata {
     pub last_used_nullifier_nonce: u64,
@@ -52,6 +431,60 @@ pub struct UserMetadata {
 
 // --- API Models ---
 
+/// Body of `POST /private/nullifiers/spent`. When the nullifiers are listed
+/// in nonce order starting at `start_nonce`, the response also reports the
+/// last used nonce so a wallet can restore its `UserMetadata`.
+#[derive(Debug, Deserialize)]
+pub struct NullifierBatchRequest {
+    pub nullifiers: Vec<String>,
+    #[serde(default)]
+    pub start_nonce: Option<u64>,
+}
+
+/// `Unknown` covers nullifiers spent before `indexed_from_block` or through
+/// an indirect (contract wallet) call, which the indexer cannot decode, as
+/// well as ones not spent at all; only `TokenPool.isNullifierSpent` can tell.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub enum NullifierState {
+    Spent,
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NullifierStatus {
+    pub nullifier: String,
+    pub status: NullifierState,
+    pub spent: bool, // same as `status == Spent`; `false` does not mean unspent
+    pub spent_at_block: Option<u64>,
+}
+
+/// Body of `POST /private/notes/unspent/batch`. The client pads
+/// `receiver_hashes` with decoys up to the fixed batch size.
+#[derive(Debug, Deserialize)]
+pub struct NoteBatchRequest {
+    pub receiver_hashes: Vec<String>,
+    #[serde(default)]
+    pub page: usize,
+}
+
+/// A fixed-width note slot. Every field is a 32-byte hex word or a single
+/// digit, so real and padding slots serialize to the same length.
+#[derive(Debug, Serialize)]
+pub struct PaddedNoteSlot {
+    pub present: u8,
+    pub note_id: String,
+    pub note_nonce: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaddedNoteResult {
+    pub receiver_hash: String,
+    pub has_more: u8,
+    pub notes: Vec<PaddedNoteSlot>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PaginatedResponse<T> {
     pub items: Vec<T>,
@@ -0,0 +1,191 @@
+// src/note_digest.rs
+//! Bloom filter over receiver hashes that still hold unspent notes.
+//!
+//! Wallets download the filter, test their own receiver hashes locally and
+//! only then fetch the note feed, so the server never learns which hashes
+//! they care about. Receiver hashes are already keccak outputs, so the bit
+//! positions are derived by double hashing over their first 16 bytes:
+//! `index_i = (h1 + i * h2) mod num_bits`, with `h1 = be_u64(hash[0..8])`
+//! and `h2 = be_u64(hash[8..16]) | 1`.
+use crate::database::Database;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Target false-positive rate at capacity; higher rates give clients more
+/// plausible deniability.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+const MIN_NUM_BITS: u64 = 64;
+const MAX_NUM_HASHES: u32 = 16;
+const MIN_CAPACITY: u64 = 64;
+const FILTER_STATE_KEY: &str = "receiver_filter_state";
+/// Cache written by earlier versions, which rebuilt the filter on request.
+const LEGACY_CACHE_KEY: &str = "receiver_filter";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiverFilter {
+    /// Feed position (block, log index) the filter reflects.
+    pub as_of: Option<(u64, u64)>,
+    pub num_items: u64,
+    pub num_bits: u64,
+    pub num_hashes: u32,
+    /// Hex bytes; bit `i` is `bytes[i / 8] >> (i % 8) & 1`.
+    pub bits: String,
+}
+
+impl ReceiverFilter {
+    /// Sizes the filter for `capacity` receivers, so later inserts keep the
+    /// target rate until it is full.
+    pub fn build(hashes: &[[u8; 32]], capacity: u64, as_of: Option<(u64, u64)>) -> Self {
+        let n = capacity.max(hashes.len() as u64).max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-n * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as u64)
+            .max(MIN_NUM_BITS)
+            .div_ceil(8)
+            * 8;
+        let num_hashes = ((num_bits as f64 / n) * ln2)
+            .round()
+            .clamp(1.0, MAX_NUM_HASHES as f64) as u32;
+
+        let mut bits = vec![0u8; (num_bits / 8) as usize];
+        for hash in hashes {
+            set_bits(&mut bits, hash, num_bits, num_hashes);
+        }
+        ReceiverFilter {
+            as_of,
+            num_items: hashes.len() as u64,
+            num_bits,
+            num_hashes,
+            bits: format!("0x{}", hex::encode(bits)),
+        }
+    }
+
+    fn insert(&mut self, hash: &[u8; 32]) -> Result<()> {
+        let mut bits = hex::decode(self.bits.strip_prefix("0x").unwrap_or(&self.bits))?;
+        set_bits(&mut bits, hash, self.num_bits, self.num_hashes);
+        self.bits = format!("0x{}", hex::encode(bits));
+        self.num_items += 1;
+        Ok(())
+    }
+}
+
+fn bit_indexes(hash: &[u8; 32], num_bits: u64, num_hashes: u32) -> impl Iterator<Item = u64> {
+    let h1 = u64::from_be_bytes(hash[..8].try_into().unwrap_or_default());
+    let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap_or_default()) | 1;
+    (0..num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+}
+
+fn set_bits(bits: &mut [u8], hash: &[u8; 32], num_bits: u64, num_hashes: u32) {
+    for index in bit_indexes(hash, num_bits, num_hashes) {
+        bits[(index / 8) as usize] |= 1 << (index % 8);
+    }
+}
+
+/// The stored filter plus what the indexer needs to keep it current.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FilterState {
+    filter: ReceiverFilter,
+    capacity: u64,
+    /// Receivers whose last unspent note has been claimed. A Bloom filter
+    /// cannot drop them, so their bits stay set until the next rebuild.
+    stale: u64,
+}
+
+fn load(db: &Database) -> Result<Option<FilterState>> {
+    match db.protocol_state.get(FILTER_STATE_KEY)? {
+        Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        None => Ok(None),
+    }
+}
+
+fn store(db: &Database, state: &FilterState) -> Result<()> {
+    db.protocol_state
+        .insert(FILTER_STATE_KEY, serde_json::to_vec(state)?)?;
+    Ok(())
+}
+
+/// Rebuilds from the unspent-note set with room to double before the next one.
+fn rebuild(db: &Database, as_of: Option<(u64, u64)>) -> Result<()> {
+    let hashes = db.active_receiver_hashes()?;
+    let capacity = (hashes.len() as u64 * 2).max(MIN_CAPACITY);
+    store(
+        db,
+        &FilterState {
+            filter: ReceiverFilter::build(&hashes, capacity, as_of),
+            capacity,
+            stale: 0,
+        },
+    )
+}
+
+/// Builds the filter on startup if the indexer has not stored one yet.
+pub fn ensure(db: &Database) -> Result<()> {
+    db.protocol_state.remove(LEGACY_CACHE_KEY)?;
+    if load(db)?.is_none() {
+        rebuild(db, db.note_feed_head()?)?;
+    }
+    Ok(())
+}
+
+/// Called by the indexer after a `NoteCreated`; `first_note` is whether the
+/// receiver had no other unspent note.
+pub fn note_created(
+    db: &Database,
+    receiver_hash: &[u8; 32],
+    first_note: bool,
+    as_of: (u64, u64),
+) -> Result<()> {
+    let Some(mut state) = load(db)? else {
+        return rebuild(db, Some(as_of));
+    };
+    if first_note {
+        if state.filter.num_items >= state.capacity {
+            return rebuild(db, Some(as_of));
+        }
+        state.filter.insert(receiver_hash)?;
+    }
+    state.filter.as_of = Some(as_of);
+    store(db, &state)
+}
+
+/// Called by the indexer after a `NoteClaimed`; `last_note` is whether the
+/// receiver has no unspent note left.
+pub fn note_claimed(db: &Database, last_note: bool, as_of: (u64, u64)) -> Result<()> {
+    let Some(mut state) = load(db)? else {
+        return rebuild(db, Some(as_of));
+    };
+    if last_note {
+        state.stale += 1;
+        // Stale receivers only add false positives; rebuild once they are a
+        // quarter of the filter.
+        if state.stale * 4 > state.filter.num_items {
+            return rebuild(db, Some(as_of));
+        }
+    }
+    state.filter.as_of = Some(as_of);
+    store(db, &state)
+}
+
+/// The filter as of the last note event the indexer applied. Read-only.
+pub fn receiver_filter(db: &Database) -> Result<ReceiverFilter> {
+    match load(db)? {
+        Some(state) => Ok(state.filter),
+        None => Ok(ReceiverFilter::build(&[], MIN_CAPACITY, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_inserts_match_a_full_build() {
+        let hashes: Vec<[u8; 32]> = (1..=20u8).map(|i| ethers::utils::keccak256([i])).collect();
+        let mut filter = ReceiverFilter::build(&hashes[..5], 40, None);
+        for hash in &hashes[5..] {
+            filter.insert(hash).unwrap();
+        }
+        let built = ReceiverFilter::build(&hashes, 40, None);
+        assert_eq!(filter.bits, built.bits);
+        assert_eq!(filter.num_items, built.num_items);
+    }
+}
@@ -0,0 +1,268 @@
+// src/rate_limit.rs
+//! Token-bucket rate limiting keyed by client IP and, for signed requests,
+//! by the derived owner pubkey.
+//!
+//! Reads (GET/HEAD) and writes draw from separate budgets. Every request is
+//! charged to its IP bucket; signed requests are also charged to the owner's
+//! bucket, so rotating IPs does not lift a single identity's limit. IPv6
+//! clients are bucketed by /64, the smallest block a host is usually given.
+//! CORS preflights are not charged.
+use crate::{api::Signer, config::Config};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Upper bound on tracked buckets; see `RateLimiter::sweep` for pruning.
+const MAX_TRACKED_BUCKETS: usize = 100_000;
+/// Entries a request inspects to make room when the table is full.
+const EVICTION_SCAN: usize = 64;
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const EXEMPT_PATHS: [&str; 3] = ["/health", "/ready", "/metrics"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Budget {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Identity {
+    Ip(IpAddr),
+    Owner([u8; 32]),
+}
+
+/// Keys an address by its IPv4 form or its IPv6 /64 prefix.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u128::MAX >> 64))),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    tokens_per_second: f64,
+    burst: f64,
+}
+
+impl Quota {
+    fn new(per_minute: u32, burst: u32) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+        Some(Self {
+            tokens_per_second: per_minute as f64 / 60.0,
+            burst: burst.max(1) as f64,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.tokens_per_second).min(quota.burst);
+        self.updated = now;
+    }
+
+    /// A full bucket carries no state worth keeping.
+    fn is_full(&self, quota: Option<Quota>, now: Instant) -> bool {
+        match quota {
+            Some(quota) => {
+                let elapsed = now.duration_since(self.updated).as_secs_f64();
+                self.tokens + elapsed * quota.tokens_per_second >= quota.burst
+            }
+            None => true,
+        }
+    }
+}
+
+pub struct RateLimiter {
+    read: Option<Quota>,
+    write: Option<Quota>,
+    trusted_proxy_hops: usize,
+    buckets: Mutex<HashMap<(Budget, Identity), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            read: Quota::new(config.rate_limit_read_per_minute, config.rate_limit_read_burst),
+            write: Quota::new(
+                config.rate_limit_write_per_minute,
+                config.rate_limit_write_burst,
+            ),
+            trusted_proxy_hops: config.trusted_proxy_hops,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn quota(&self, budget: Budget) -> Option<Quota> {
+        match budget {
+            Budget::Read => self.read,
+            Budget::Write => self.write,
+        }
+    }
+
+    /// Takes one token, or returns how long until one is available.
+    fn acquire(&self, budget: Budget, identity: Identity) -> Result<(), Duration> {
+        let Some(quota) = self.quota(budget) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_BUCKETS && !buckets.contains_key(&(budget, identity)) {
+            // Bounded work per request: drop the full buckets among a sample,
+            // or failing that the least recently used one in it.
+            let sample: Vec<_> = buckets.iter().take(EVICTION_SCAN).map(|(key, bucket)| (*key, *bucket)).collect();
+            let full: Vec<_> = sample
+                .iter()
+                .filter(|(key, bucket)| bucket.is_full(self.quota(key.0), now))
+                .map(|(key, _)| *key)
+                .collect();
+            if full.is_empty() {
+                if let Some((key, _)) = sample.iter().min_by_key(|(_, bucket)| bucket.updated) {
+                    buckets.remove(key);
+                }
+            }
+            for key in full {
+                buckets.remove(&key);
+            }
+        }
+        let bucket = buckets.entry((budget, identity)).or_insert(Bucket {
+            tokens: quota.burst,
+            updated: now,
+        });
+        bucket.refill(&quota, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / quota.tokens_per_second;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    /// Drops full buckets. Run every `SWEEP_INTERVAL` from a background task,
+    /// so requests never pay for a full pass over the table.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|(budget, _), bucket| !bucket.is_full(self.quota(*budget), now));
+    }
+
+    /// Each trusted proxy appends the address it was reached from, so the
+    /// client is `trusted_proxy_hops` entries from the right; anything further
+    /// left was written by the client. A chain shorter than that did not come
+    /// through every proxy and falls back to the peer.
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        if self.trusted_proxy_hops == 0 {
+            return peer.ip();
+        }
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .collect();
+        forwarded
+            .len()
+            .checked_sub(self.trusted_proxy_hops)
+            .and_then(|i| forwarded[i].trim().parse().ok())
+            .unwrap_or(peer.ip())
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.to_string())],
+    )
+        .into_response()
+}
+
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if EXEMPT_PATHS.contains(&request.uri().path()) || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+    let budget = match *request.method() {
+        Method::GET | Method::HEAD => Budget::Read,
+        _ => Budget::Write,
+    };
+
+    let ip = client_key(limiter.client_ip(request.headers(), peer));
+    if let Err(retry_after) = limiter.acquire(budget, Identity::Ip(ip)) {
+        return too_many_requests(retry_after);
+    }
+    // Unsigned or badly signed requests are left for the handler to reject.
+    if request.headers().contains_key("x-signature") {
+        if let Ok(signer) = Signer::recover(request.headers()).await {
+            if let Err(retry_after) = limiter.acquire(budget, Identity::Owner(signer.owner_pub_key())) {
+                return too_many_requests(retry_after);
+            }
+            // Handlers take the signer from here instead of recovering it again.
+            request.extensions_mut().insert(signer);
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_clients_share_a_bucket_per_64() {
+        let a: IpAddr = "2001:db8:1:2:aaaa::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:1:2:bbbb::2".parse().unwrap();
+        let other: IpAddr = "2001:db8:1:3::1".parse().unwrap();
+        assert_eq!(client_key(a), client_key(b));
+        assert_ne!(client_key(a), client_key(other));
+
+        let mapped: IpAddr = "::ffff:192.0.2.7".parse().unwrap();
+        assert_eq!(client_key(mapped), "192.0.2.7".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn spoofed_forwarded_entries_share_the_real_clients_bucket() {
+        let limiter = RateLimiter {
+            read: Quota::new(1, 1),
+            write: None,
+            trusted_proxy_hops: 1,
+            buckets: Mutex::new(HashMap::new()),
+        };
+        let peer: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let request = |forwarded: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", forwarded.parse().unwrap());
+            let ip = client_key(limiter.client_ip(&headers, peer));
+            limiter.acquire(Budget::Read, Identity::Ip(ip))
+        };
+        assert!(request("198.51.100.1, 203.0.113.9").is_ok());
+        assert!(request("198.51.100.2, 203.0.113.9").is_err());
+        assert!(request("203.0.113.10").is_ok());
+    }
+}
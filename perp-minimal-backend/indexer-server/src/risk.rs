@@ -0,0 +1,135 @@
+// src/risk.rs
+//! Mirrors the ClearingHouseV2 solvency math, via `keeper_tx::risk`, so the
+//! API can report PnL and risk figures without an RPC call per position.
+use crate::models::{MarkPrice, Position, PositionMetrics, ProtocolConstants};
+use anyhow::Result;
+use ethers::types::{I256, U256};
+use keeper_tx::risk::{MarginParams, PositionTerms};
+
+pub fn compute_metrics(
+    position: &Position,
+    mark: &MarkPrice,
+    constants: &ProtocolConstants,
+) -> Result<PositionMetrics> {
+    let terms = PositionTerms {
+        is_long: position.is_long,
+        size: U256::from_dec_str(&position.size)?,
+        margin: U256::from_dec_str(&position.margin)?,
+        entry_price: U256::from_dec_str(&position.entry_price)?,
+    };
+    let mark_price = U256::from_dec_str(&mark.price)?;
+    let bps_divisor = U256::from(constants.bps_divisor);
+    let params = MarginParams {
+        price_precision: U256::from(constants.price_precision),
+        bps_divisor,
+        maintenance_margin_ratio_bps: U256::from(constants.maintenance_margin_ratio_bps),
+    };
+
+    let pnl = terms.pnl(mark_price, &params);
+    let notional = terms.notional(mark_price, &params);
+    let close_fee = notional * U256::from(constants.taker_fee_bps) / bps_divisor;
+    let maintenance_margin = terms.maintenance_margin(mark_price, &params);
+    let equity = terms.equity(mark_price, &params);
+    let is_solvent = terms.is_solvent(mark_price, &params);
+
+    let leverage_bps = if equity > I256::zero() {
+        Some((notional * bps_divisor / equity.into_raw()).to_string())
+    } else {
+        None
+    };
+    let margin_ratio_bps = if notional.is_zero() {
+        None
+    } else {
+        Some((equity * I256::from_raw(bps_divisor) / I256::from_raw(notional)).to_string())
+    };
+
+    Ok(PositionMetrics {
+        mark_price: mark_price.to_string(),
+        notional_value: notional.to_string(),
+        unrealized_pnl: pnl.to_string(),
+        estimated_close_fee: close_fee.to_string(),
+        equity: equity.to_string(),
+        leverage_bps,
+        margin_ratio_bps,
+        maintenance_margin: maintenance_margin.to_string(),
+        liquidation_price: terms.liquidation_price(&params).map(|p| p.to_string()),
+        is_solvent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small precision so the expected figures can be checked by hand.
+    fn constants() -> ProtocolConstants {
+        ProtocolConstants {
+            maintenance_margin_ratio_bps: 245,
+            price_precision: 100,
+            taker_fee_bps: 10,
+            bps_divisor: 10_000,
+        }
+    }
+
+    fn position(is_long: bool, size: u64, margin: u64, entry_price: u64) -> Position {
+        Position {
+            position_id: "0x01".to_string(),
+            is_long,
+            entry_price: entry_price.to_string(),
+            margin: margin.to_string(),
+            size: size.to_string(),
+        }
+    }
+
+    fn metrics(position: &Position, price: u64) -> PositionMetrics {
+        let mark = MarkPrice { price: price.to_string(), timestamp: 0 };
+        compute_metrics(position, &mark, &constants()).unwrap()
+    }
+
+    #[test]
+    fn pnl_truncates_toward_zero() {
+        // -150 / 100 is -1 in Solidity, not the floored -2.
+        assert_eq!(metrics(&position(true, 150, 1_000, 100), 99).unrealized_pnl, "-1");
+        assert_eq!(metrics(&position(false, 150, 1_000, 100), 101).unrealized_pnl, "-1");
+        assert_eq!(metrics(&position(false, 150, 1_000, 100), 99).unrealized_pnl, "1");
+        assert_eq!(metrics(&position(true, 3, 1_000, 100), 99).unrealized_pnl, "0");
+    }
+
+    #[test]
+    fn equity_equal_to_maintenance_margin_is_insolvent() {
+        // Notional 1_000_000, maintenance 1_000_000 * 245 / 10_000 = 24_500.
+        let at_boundary = metrics(&position(true, 10_000, 24_500, 10_000), 10_000);
+        assert_eq!(at_boundary.maintenance_margin, "24500");
+        assert_eq!(at_boundary.equity, "24500");
+        assert!(!at_boundary.is_solvent);
+        assert!(metrics(&position(true, 10_000, 24_501, 10_000), 10_000).is_solvent);
+    }
+
+    #[test]
+    fn liquidation_price_is_the_last_insolvent_price() {
+        let long = position(true, 1_000, 50_000, 10_000);
+        let price: u64 = metrics(&long, 10_000).liquidation_price.unwrap().parse().unwrap();
+        assert_eq!(price, 5_125);
+        assert!(!metrics(&long, price).is_solvent);
+        assert!(metrics(&long, price + 1).is_solvent);
+
+        let short = position(false, 1_000, 50_000, 10_000);
+        let price: u64 = metrics(&short, 10_000).liquidation_price.unwrap().parse().unwrap();
+        assert!(metrics(&short, price - 1).is_solvent);
+        assert!(!metrics(&short, price + 1).is_solvent);
+    }
+
+    #[test]
+    fn overcollateralized_long_has_no_liquidation_price() {
+        assert_eq!(metrics(&position(true, 1_000, 100_000, 10_000), 10_000).liquidation_price, None);
+    }
+
+    #[test]
+    fn close_fee_is_charged_on_mark_notional() {
+        // Notional at mark 12_000 is 120_000; 10 bps of it is 120.
+        let m = metrics(&position(true, 1_000, 50_000, 10_000), 12_000);
+        assert_eq!(m.notional_value, "120000");
+        assert_eq!(m.estimated_close_fee, "120");
+        assert_eq!(m.leverage_bps.as_deref(), Some("17142"));
+    }
+}
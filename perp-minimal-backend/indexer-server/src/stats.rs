@@ -0,0 +1,188 @@
+// src/stats.rs
+//! Folds position lifecycle events into the running `ProtocolTotals`.
+use crate::{
+    database::Database,
+    models::{Position, ProtocolTotals, TradeOutcome},
+};
+use anyhow::Result;
+use ethers::types::{I256, U256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_HOUR: u64 = 3_600;
+
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn current_hour() -> u64 {
+    current_timestamp() / SECONDS_PER_HOUR
+}
+
+pub fn hour_of(timestamp: u64) -> u64 {
+    timestamp / SECONDS_PER_HOUR
+}
+
+struct PositionAmounts {
+    size: U256,
+    margin: U256,
+    entry_notional: U256,
+}
+
+fn position_amounts(db: &Database, position: &Position) -> Result<PositionAmounts> {
+    let size = U256::from_dec_str(&position.size)?;
+    let margin = U256::from_dec_str(&position.margin)?;
+    let entry_price = U256::from_dec_str(&position.entry_price)?;
+    Ok(PositionAmounts {
+        size,
+        margin,
+        entry_notional: size * entry_price / price_precision(db)?,
+    })
+}
+
+fn price_precision(db: &Database) -> Result<U256> {
+    Ok(db
+        .get_protocol_constants()?
+        .map(|c| U256::from(c.price_precision))
+        .unwrap_or_else(|| U256::exp10(18)))
+}
+
+/// Adds volume to the hour of the event's block, so replayed and backfilled
+/// events land in the bucket they happened in.
+fn record_volume(db: &Database, volume: U256, is_private: bool, timestamp: u64) -> Result<()> {
+    db.add_hourly_volume(hour_of(timestamp), volume)?;
+    db.update_protocol_totals(|totals| {
+        totals.cumulative_volume += volume;
+        if is_private {
+            totals.private_volume += volume;
+        } else {
+            totals.public_volume += volume;
+        }
+    })
+}
+
+/// The opening fee is not emitted; it is charged on the entry notional.
+/// `None` until the protocol constants have been read.
+pub fn opening_fee(db: &Database, position: &Position) -> Result<Option<U256>> {
+    let amounts = position_amounts(db, position)?;
+    Ok(db.get_protocol_constants()?.map(|c| {
+        amounts.entry_notional * U256::from(c.taker_fee_bps) / U256::from(c.bps_divisor)
+    }))
+}
+
+pub fn record_position_opened(
+    db: &Database,
+    position: &Position,
+    is_private: bool,
+    timestamp: u64,
+) -> Result<()> {
+    let amounts = position_amounts(db, position)?;
+    let fee = opening_fee(db, position)?;
+    db.update_protocol_totals(|totals| {
+        if position.is_long {
+            totals.long_open_interest += amounts.size;
+        } else {
+            totals.short_open_interest += amounts.size;
+        }
+        totals.open_position_count += 1;
+        totals.open_margin += amounts.margin;
+        if let Some(fee) = fee {
+            totals.taker_fees += fee;
+        }
+        if is_private {
+            totals.private_positions_opened += 1;
+        } else {
+            totals.public_positions_opened += 1;
+        }
+    })?;
+    record_volume(db, amounts.entry_notional, is_private, timestamp)
+}
+
+fn remove_open_position(totals: &mut ProtocolTotals, position: &Position, amounts: &PositionAmounts) {
+    if position.is_long {
+        totals.long_open_interest = totals.long_open_interest.saturating_sub(amounts.size);
+    } else {
+        totals.short_open_interest = totals.short_open_interest.saturating_sub(amounts.size);
+    }
+    totals.open_position_count = totals.open_position_count.saturating_sub(1);
+    totals.open_margin = totals.open_margin.saturating_sub(amounts.margin);
+}
+
+pub fn record_position_closed(
+    db: &Database,
+    position: &Position,
+    pnl: I256,
+    fee: U256,
+    is_private: bool,
+    timestamp: u64,
+) -> Result<TradeOutcome> {
+    let amounts = position_amounts(db, position)?;
+    // size * exit / precision == entry notional +/- pnl, up to rounding.
+    let signed_pnl = if position.is_long { pnl } else { -pnl };
+    let exit_notional = I256::from_raw(amounts.entry_notional) + signed_pnl;
+    let exit_notional = if exit_notional > I256::zero() {
+        exit_notional.into_raw()
+    } else {
+        U256::zero()
+    };
+    db.update_protocol_totals(|totals| {
+        remove_open_position(totals, position, &amounts);
+        totals.taker_fees += fee;
+    })?;
+    record_volume(db, exit_notional, is_private, timestamp)?;
+    Ok(TradeOutcome {
+        realized_pnl: net_pnl(pnl, fee),
+        margin: amounts.margin,
+        volume: amounts.entry_notional + exit_notional,
+    })
+}
+
+/// Realized PnL of a close net of its fee. The leaderboard and the position
+/// history both rank closes by this figure.
+pub fn net_pnl(pnl: I256, fee: U256) -> I256 {
+    pnl - I256::from_raw(fee)
+}
+
+/// `mark_price` is the oracle price in effect when the position was
+/// liquidated; without one the notional falls back to the entry notional.
+pub fn record_position_liquidated(
+    db: &Database,
+    position: &Position,
+    liquidation_fee: U256,
+    is_private: bool,
+    timestamp: u64,
+    mark_price: Option<U256>,
+) -> Result<TradeOutcome> {
+    let amounts = position_amounts(db, position)?;
+    let liquidation_notional = match mark_price {
+        Some(price) => amounts.size * price / price_precision(db)?,
+        None => amounts.entry_notional,
+    };
+    db.update_protocol_totals(|totals| {
+        remove_open_position(totals, position, &amounts);
+        totals.liquidation_count += 1;
+        totals.liquidation_volume += liquidation_notional;
+        totals.liquidation_fees += liquidation_fee;
+    })?;
+    record_volume(db, liquidation_notional, is_private, timestamp)?;
+    // A liquidated trader forfeits their whole margin.
+    Ok(TradeOutcome {
+        realized_pnl: -I256::from_raw(amounts.margin),
+        margin: amounts.margin,
+        volume: amounts.entry_notional + liquidation_notional,
+    })
+}
+
+pub fn record_margin_change(db: &Database, amount: U256, is_addition: bool) -> Result<()> {
+    db.update_protocol_totals(|totals| {
+        if is_addition {
+            totals.open_margin += amount;
+            totals.margin_added += amount;
+        } else {
+            totals.open_margin = totals.open_margin.saturating_sub(amount);
+            totals.margin_removed += amount;
+        }
+    })
+}
@@ -0,0 +1,230 @@
+// src/status.rs
+//! In-memory sync progress shared by the indexer and the `/ready` and
+//! `/status` endpoints. Nothing here is persisted; a restart starts over at
+//! `Starting`.
+use crate::stats;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+    Starting,
+    Backfilling,
+    Realtime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum StreamState {
+    Connecting,
+    Live { last_event_at: Option<u64> },
+    Error {
+        error: String,
+        at: u64,
+        last_event_at: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct BlockRef {
+    number: u64,
+    timestamp: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    phase: SyncPhase,
+    backfill_target: Option<u64>,
+    last_indexed: Option<BlockRef>,
+    head: Option<BlockRef>,
+    /// Wall-clock time the current head was received.
+    head_received_at: Option<u64>,
+    /// Head as of the previous realtime checkpoint.
+    checkpoint_head: Option<BlockRef>,
+    /// Wall-clock time of the previous realtime checkpoint.
+    checkpoint_at: Option<u64>,
+    streams: BTreeMap<&'static str, StreamState>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncSnapshot {
+    pub phase: SyncPhase,
+    pub backfill_target: Option<u64>,
+    pub last_indexed_block: Option<u64>,
+    pub head_block: Option<u64>,
+    pub lag_blocks: Option<u64>,
+    pub lag_seconds: Option<u64>,
+    pub head_age_seconds: Option<u64>,
+    pub streams: BTreeMap<&'static str, StreamState>,
+}
+
+impl SyncSnapshot {
+    pub fn has_stream_errors(&self) -> bool {
+        self.streams
+            .values()
+            .any(|state| matches!(state, StreamState::Error { .. }))
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncStatus {
+    inner: Mutex<Inner>,
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                phase: SyncPhase::Starting,
+                backfill_target: None,
+                last_indexed: None,
+                head: None,
+                head_received_at: None,
+                checkpoint_head: None,
+                checkpoint_at: None,
+                streams: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl SyncStatus {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn start_backfill(&self, target_block: u64) {
+        let mut inner = self.lock();
+        inner.phase = SyncPhase::Backfilling;
+        inner.backfill_target = Some(target_block);
+    }
+
+    pub fn start_realtime(&self) {
+        self.lock().phase = SyncPhase::Realtime;
+    }
+
+    /// Records that every event up to and including `block` was handled.
+    pub fn advance_indexed(&self, block: u64, timestamp: u64) {
+        let mut inner = self.lock();
+        if inner.last_indexed.is_none_or(|b| b.number < block) {
+            inner.last_indexed = Some(BlockRef {
+                number: block,
+                timestamp,
+            });
+        }
+    }
+
+    pub fn last_indexed_block(&self) -> Option<u64> {
+        self.lock().last_indexed.map(|b| b.number)
+    }
+
+    pub fn set_head(&self, block: u64, timestamp: u64) {
+        let mut inner = self.lock();
+        if inner.head.is_none_or(|b| b.number < block) {
+            inner.head = Some(BlockRef {
+                number: block,
+                timestamp,
+            });
+            inner.head_received_at = Some(stats::current_timestamp());
+        }
+    }
+
+    /// Called periodically in realtime mode. Log filters poll the node, so
+    /// only a head seen one checkpoint ago is known to have been delivered.
+    ///
+    /// A stream error holds progress back for the checkpoint it falls in.
+    /// The stream has polled again by the next one, so an error older than
+    /// the previous checkpoint is cleared rather than waiting for that
+    /// stream's next event.
+    pub fn checkpoint(&self) {
+        let now = stats::current_timestamp();
+        let mut inner = self.lock();
+        let since = inner.checkpoint_at;
+        let healthy = !inner.streams.values().any(|state| match state {
+            StreamState::Error { at, .. } => since.is_none_or(|since| *at >= since),
+            _ => false,
+        });
+        if let (true, Some(previous)) = (healthy, inner.checkpoint_head) {
+            if inner.last_indexed.is_none_or(|b| b.number < previous.number) {
+                inner.last_indexed = Some(previous);
+            }
+        }
+        if let Some(since) = since {
+            for state in inner.streams.values_mut() {
+                if let StreamState::Error { at, last_event_at, .. } = *state {
+                    if at < since {
+                        *state = StreamState::Live { last_event_at };
+                    }
+                }
+            }
+        }
+        inner.checkpoint_head = inner.head;
+        inner.checkpoint_at = Some(now);
+    }
+
+    pub fn stream_connecting(&self, name: &'static str) {
+        self.lock().streams.insert(name, StreamState::Connecting);
+    }
+
+    pub fn stream_event(&self, name: &'static str) {
+        self.lock().streams.insert(
+            name,
+            StreamState::Live {
+                last_event_at: Some(stats::current_timestamp()),
+            },
+        );
+    }
+
+    /// A stream that has subscribed but not yet delivered anything.
+    pub fn stream_live(&self, name: &'static str) {
+        self.lock().streams.insert(
+            name,
+            StreamState::Live {
+                last_event_at: None,
+            },
+        );
+    }
+
+    pub fn stream_error(&self, name: &'static str, error: &dyn std::fmt::Display) {
+        let mut inner = self.lock();
+        let last_event_at = match inner.streams.get(name) {
+            Some(StreamState::Live { last_event_at } | StreamState::Error { last_event_at, .. }) => *last_event_at,
+            _ => None,
+        };
+        inner.streams.insert(
+            name,
+            StreamState::Error {
+                error: error.to_string(),
+                at: stats::current_timestamp(),
+                last_event_at,
+            },
+        );
+    }
+
+    pub fn snapshot(&self) -> SyncSnapshot {
+        let inner = self.lock();
+        let lag = match (inner.head, inner.last_indexed) {
+            (Some(head), Some(indexed)) => Some((
+                head.number.saturating_sub(indexed.number),
+                head.timestamp.saturating_sub(indexed.timestamp),
+            )),
+            _ => None,
+        };
+        SyncSnapshot {
+            phase: inner.phase,
+            backfill_target: inner.backfill_target,
+            last_indexed_block: inner.last_indexed.map(|b| b.number),
+            head_block: inner.head.map(|b| b.number),
+            lag_blocks: lag.map(|(blocks, _)| blocks),
+            lag_seconds: lag.map(|(_, seconds)| seconds),
+            head_age_seconds: inner
+                .head_received_at
+                .map(|at| stats::current_timestamp().saturating_sub(at)),
+            streams: inner.streams.clone(),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// src/tls.rs
+//! Optional rustls termination for the API server, with certificate reload.
+use crate::config::Config;
+use anyhow::Result;
+use axum_server::tls_rustls::RustlsConfig;
+use std::time::{Duration, SystemTime};
+use tokio::time::sleep;
+
+/// Loads the configured certificate and key, or `None` when TLS is disabled.
+pub async fn load(config: &Config) -> Result<Option<RustlsConfig>> {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Ok(None);
+    };
+    // Only the ring backend is compiled in; ignore "already installed".
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let tls_config = RustlsConfig::from_pem_file(cert_path, key_path).await?;
+    Ok(Some(tls_config))
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Polls the certificate and key files and swaps them into `tls_config`
+/// when either changes. A failed reload keeps serving the previous pair.
+pub async fn watch_certificates(tls_config: RustlsConfig, config: &Config) {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return;
+    };
+    let interval = Duration::from_secs(config.tls_reload_interval_secs.max(1));
+    let mut last_seen = (modified(cert_path), modified(key_path));
+    loop {
+        sleep(interval).await;
+        let current = (modified(cert_path), modified(key_path));
+        if current == last_seen {
+            continue;
+        }
+        match tls_config.reload_from_pem_file(cert_path, key_path).await {
+            Ok(()) => {
+                println!("[API] Reloaded TLS certificate from {}", cert_path);
+                last_seen = current;
+            }
+            // Retried next tick, e.g. when only one of the files was replaced so far.
+            Err(e) => eprintln!("[API ERROR] Failed to reload TLS certificate: {}", e),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// src/fees.rs
+//! EIP-1559 fee policy: a capped max fee derived from the current base fee,
+//! a priority tip per urgency tier, and the bump applied to replacements.
+use anyhow::Result;
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction, utils::parse_units};
+use std::env;
+
+/// Nodes reject replacements that raise fees by less than 10%.
+const MIN_BUMP_PERCENT: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    High,
+}
+
+impl std::str::FromStr for Urgency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Urgency::Low),
+            "normal" => Ok(Urgency::Normal),
+            "high" => Ok(Urgency::High),
+            other => Err(anyhow::anyhow!("unknown urgency tier '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fees {
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+}
+
+impl Fees {
+    pub fn apply(&self, tx: &mut TypedTransaction) {
+        match tx {
+            TypedTransaction::Eip1559(inner) => {
+                inner.max_fee_per_gas = Some(self.max_fee_per_gas);
+                inner.max_priority_fee_per_gas = Some(self.max_priority_fee_per_gas);
+            }
+            _ => {
+                tx.set_gas_price(self.max_fee_per_gas);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeePolicy {
+    pub max_fee_cap: U256,
+    pub priority_fee_low: U256,
+    pub priority_fee_normal: U256,
+    pub priority_fee_high: U256,
+    /// Tier for routine transactions; callers escalate urgent ones to `High`.
+    pub default_urgency: Urgency,
+    /// Headroom over the current base fee, so a fee survives rising blocks.
+    pub base_fee_multiplier: u64,
+    pub bump_percent: u64,
+    /// Blocks a broadcast may stay pending before it is replaced.
+    pub replace_after_blocks: u64,
+    /// Rounds of `replace_after_blocks` before giving up, counting those
+    /// the fee cap leaves without a bump.
+    pub max_replacements: u32,
+}
+
+fn gwei_env(name: &str, default: &str) -> Result<U256> {
+    let value = env::var(name).unwrap_or_else(|_| default.to_string());
+    Ok(parse_units(value, "gwei")?.into())
+}
+
+fn parsed_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T>
+where
+    T::Err: Into<anyhow::Error>,
+{
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(Into::into),
+        Err(_) => Ok(default),
+    }
+}
+
+impl FeePolicy {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            max_fee_cap: gwei_env("MAX_FEE_PER_GAS_GWEI", "500")?,
+            priority_fee_low: gwei_env("PRIORITY_FEE_LOW_GWEI", "0.5")?,
+            priority_fee_normal: gwei_env("PRIORITY_FEE_NORMAL_GWEI", "1.5")?,
+            priority_fee_high: gwei_env("PRIORITY_FEE_HIGH_GWEI", "5")?,
+            default_urgency: parsed_env("FEE_URGENCY", Urgency::Normal)?,
+            base_fee_multiplier: parsed_env("BASE_FEE_MULTIPLIER", 2)?,
+            bump_percent: parsed_env("FEE_BUMP_PERCENT", 15u64)?.max(MIN_BUMP_PERCENT),
+            replace_after_blocks: parsed_env("REPLACE_AFTER_BLOCKS", 3)?,
+            max_replacements: parsed_env("MAX_FEE_BUMPS", 5)?,
+        })
+    }
+
+    fn priority_fee(&self, urgency: Urgency) -> U256 {
+        match urgency {
+            Urgency::Low => self.priority_fee_low,
+            Urgency::Normal => self.priority_fee_normal,
+            Urgency::High => self.priority_fee_high,
+        }
+    }
+
+    /// Fees for a new transaction at the current base fee. Chains without a
+    /// base fee get the node's legacy gas price for both fields.
+    pub async fn fees<M: Middleware>(&self, client: &M, urgency: Urgency) -> Result<Fees> {
+        let base_fee = client
+            .get_block(BlockNumber::Latest)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .and_then(|block| block.base_fee_per_gas);
+        let Some(base_fee) = base_fee else {
+            let gas_price = client
+                .get_gas_price()
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+                .min(self.max_fee_cap);
+            return Ok(Fees { max_fee_per_gas: gas_price, max_priority_fee_per_gas: gas_price });
+        };
+        let max_fee_per_gas = (base_fee * self.base_fee_multiplier + self.priority_fee(urgency))
+            .min(self.max_fee_cap);
+        Ok(Fees {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: self.priority_fee(urgency).min(max_fee_per_gas),
+        })
+    }
+
+    /// Fees for a replacement of a transaction sent with `fees`, or `None`
+    /// once the cap leaves no room for a bump the node would accept.
+    pub fn bump(&self, fees: Fees) -> Option<Fees> {
+        let bump = |fee: U256| fee * (100 + self.bump_percent) / 100 + 1;
+        let bumped = Fees {
+            max_fee_per_gas: bump(fees.max_fee_per_gas),
+            max_priority_fee_per_gas: bump(fees.max_priority_fee_per_gas),
+        };
+        (bumped.max_fee_per_gas <= self.max_fee_cap).then_some(bumped)
+    }
+}
@@ -0,0 +1,7 @@
+// src/lib.rs
+//! Code shared by the liquidation and oracle bots and the indexer: the
+//! EIP-1559 fee policy, the tracker that replaces stuck broadcasts, and the
+//! ClearingHouseV2 solvency math.
+pub mod fees;
+pub mod risk;
+pub mod tx_tracker;
@@ -0,0 +1,149 @@
+// src/risk.rs
+//! ClearingHouseV2 solvency math (`_calculatePnl` and the maintenance margin
+//! check), shared by the liquidation bot and the indexer so both agree with
+//! the contract to the unit.
+use ethers::types::{I256, U256};
+
+/// The contract constants the solvency check depends on.
+#[derive(Debug, Clone, Copy)]
+pub struct MarginParams {
+    pub price_precision: U256,
+    pub bps_divisor: U256,
+    pub maintenance_margin_ratio_bps: U256,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PositionTerms {
+    pub is_long: bool,
+    pub size: U256,
+    pub margin: U256,
+    pub entry_price: U256,
+}
+
+impl PositionTerms {
+    /// Signed division truncates toward zero like Solidity.
+    pub fn pnl(&self, price: U256, params: &MarginParams) -> I256 {
+        let price_delta = if self.is_long {
+            I256::from_raw(price) - I256::from_raw(self.entry_price)
+        } else {
+            I256::from_raw(self.entry_price) - I256::from_raw(price)
+        };
+        price_delta * I256::from_raw(self.size) / I256::from_raw(params.price_precision)
+    }
+
+    pub fn notional(&self, price: U256, params: &MarginParams) -> U256 {
+        self.size * price / params.price_precision
+    }
+
+    pub fn maintenance_margin(&self, price: U256, params: &MarginParams) -> U256 {
+        self.notional(price, params) * params.maintenance_margin_ratio_bps / params.bps_divisor
+    }
+
+    pub fn equity(&self, price: U256, params: &MarginParams) -> I256 {
+        I256::from_raw(self.margin) + self.pnl(price, params)
+    }
+
+    pub fn is_solvent(&self, price: U256, params: &MarginParams) -> bool {
+        self.equity(price, params) > I256::from_raw(self.maintenance_margin(price, params))
+    }
+
+    /// Solves `margin + pnl(P) == notional(P) * mmr / bps` for the price `P`,
+    /// floored.
+    ///
+    /// Long:  P = (entry * size - margin * precision) * bps / (size * (bps - mmr))
+    /// Short: P = (entry * size + margin * precision) * bps / (size * (bps + mmr))
+    ///
+    /// The contract rounds the required margin down, which favours the
+    /// position, but truncates PnL toward zero, which costs it up to one unit
+    /// of equity whenever PnL is positive at the threshold. That is the case
+    /// once leverage exceeds `bps / mmr` (about 40x at 245 bps), so such a
+    /// position can be insolvent a little past this price; see
+    /// `liquidation_bound`. Returns `None` when no positive price liquidates
+    /// the position.
+    pub fn liquidation_price(&self, params: &MarginParams) -> Option<U256> {
+        self.threshold(params, U256::zero())
+    }
+
+    /// The same threshold with one unit less equity, so a long is never
+    /// insolvent above it and a short never at or below it, whatever the
+    /// truncation. Suited to indexing candidates that are then checked with
+    /// `is_solvent`.
+    pub fn liquidation_bound(&self, params: &MarginParams) -> Option<U256> {
+        self.threshold(params, params.price_precision)
+    }
+
+    /// `slack` is equity given up, scaled by the price precision.
+    fn threshold(&self, params: &MarginParams, slack: U256) -> Option<U256> {
+        if self.size.is_zero() {
+            return None;
+        }
+        let bps = params.bps_divisor;
+        let mmr = params.maintenance_margin_ratio_bps;
+        let entry_value = self.entry_price * self.size;
+        let margin_value = self.margin * params.price_precision;
+        if self.is_long {
+            let numerator = (entry_value + slack).checked_sub(margin_value)?;
+            if numerator.is_zero() || mmr >= bps {
+                return None;
+            }
+            Some(numerator * bps / (self.size * (bps - mmr)))
+        } else {
+            let numerator = (entry_value + margin_value).saturating_sub(slack);
+            Some(numerator * bps / (self.size * (bps + mmr)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> MarginParams {
+        MarginParams {
+            price_precision: U256::from(100),
+            bps_divisor: U256::from(10_000),
+            maintenance_margin_ratio_bps: U256::from(500),
+        }
+    }
+
+    fn terms(is_long: bool, size: u64, margin: u64) -> PositionTerms {
+        PositionTerms {
+            is_long,
+            size: U256::from(size),
+            margin: U256::from(margin),
+            entry_price: U256::from(10_000),
+        }
+    }
+
+    #[test]
+    fn truncated_profit_can_liquidate_past_the_liquidation_price() {
+        let params = params();
+        // ~1_100x: in profit at the threshold, where 1.01 of PnL counts as 1.
+        let long = terms(true, 101, 9);
+        let price = long.liquidation_price(&params).unwrap();
+        assert_eq!(price, U256::from(10_516));
+        assert!(!long.is_solvent(price + 1, &params));
+        assert_eq!(long.liquidation_bound(&params), Some(price + 1));
+    }
+
+    #[test]
+    fn liquidation_bound_covers_every_insolvent_price() {
+        let params = params();
+        for size in [101, 150, 999, 1_000] {
+            for margin in [0, 1, 9, 27, 500, 5_000, 50_000] {
+                for is_long in [true, false] {
+                    let position = terms(is_long, size, margin);
+                    let bound = position.liquidation_bound(&params);
+                    for price in (8_000..12_500).map(U256::from) {
+                        if position.is_solvent(price, &params) {
+                            continue;
+                        }
+                        let bound = bound.unwrap_or_else(|| panic!("{size}/{margin} has no bound"));
+                        let covered = if is_long { price <= bound } else { price > bound };
+                        assert!(covered, "{is_long} {size}/{margin} insolvent at {price}, bound {bound}");
+                    }
+                }
+            }
+        }
+    }
+}
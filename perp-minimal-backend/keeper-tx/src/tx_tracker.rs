@@ -0,0 +1,264 @@
+// src/tx_tracker.rs
+//! Broadcasts a transaction at policy fees and watches it until one of its
+//! broadcasts is mined. A broadcast still pending after
+//! `replace_after_blocks` is resent at bumped fees under the same nonce, or
+//! replaced by a zero-value self-transfer once the caller no longer wants it.
+//! RPC errors while watching are logged and retried on the next poll; the
+//! watch ends on a receipt, on the nonce being used elsewhere, or after
+//! `max_replacements` rounds of `replace_after_blocks`. A round the fee cap
+//! leaves without a bump still counts, so a capped transaction gives up too.
+use crate::fees::{FeePolicy, Urgency};
+use anyhow::{anyhow, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use std::{future::Future, time::Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const CANCEL_GAS: u64 = 21_000;
+
+#[derive(Debug)]
+pub enum TxOutcome {
+    /// One of the broadcasts of the original transaction was mined.
+    Mined(TransactionReceipt),
+    /// The cancelling self-transfer was mined.
+    Cancelled(TransactionReceipt),
+    /// The nonce was used by a transaction this tracker did not send.
+    NonceConsumed,
+}
+
+/// Sends `tx`, which must already carry its nonce, sender and gas limit
+/// (see `Middleware::fill_transaction`). `on_broadcast` sees the hash of
+/// every broadcast, including replacements. `is_obsolete` is asked before
+/// each replacement whether the transaction should be cancelled instead.
+pub async fn send_tracked<M, F, Fut>(
+    client: &M,
+    mut tx: TypedTransaction,
+    policy: &FeePolicy,
+    urgency: Urgency,
+    mut is_obsolete: F,
+    mut on_broadcast: impl FnMut(H256),
+) -> Result<TxOutcome>
+where
+    M: Middleware,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let nonce = *tx.nonce().ok_or_else(|| anyhow!("transaction has no nonce"))?;
+    let from = *tx.from().ok_or_else(|| anyhow!("transaction has no sender"))?;
+    let mut fees = policy.fees(client, urgency).await?;
+    fees.apply(&mut tx);
+
+    // (hash, is_cancellation) of every broadcast sharing this nonce.
+    let mut broadcasts: Vec<(H256, bool)> = Vec::new();
+    let hash = broadcast(client, &tx).await?;
+    on_broadcast(hash);
+    broadcasts.push((hash, false));
+    let mut cancelling = false;
+    let mut rounds = 0;
+    // Taken from the first poll if the node cannot be asked right away.
+    let mut sent_at_block = block_number(client).await.ok();
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let polled = match poll(client, from, nonce, &broadcasts).await {
+            Ok(Some(outcome)) => return Ok(outcome),
+            Ok(None) => block_number(client).await,
+            Err(e) => Err(e),
+        };
+        let block = match polled {
+            Ok(block) => block,
+            Err(e) => {
+                eprintln!("[ERROR] Failed to check transaction with nonce {}, retrying: {}", nonce, e);
+                continue;
+            }
+        };
+        let sent_at = *sent_at_block.get_or_insert(block);
+        if block < sent_at + policy.replace_after_blocks {
+            continue;
+        }
+        if rounds >= policy.max_replacements {
+            return Err(anyhow!("nonce {} still pending after {} replacement round(s)", nonce, rounds));
+        }
+        rounds += 1;
+        let Some(bumped) = policy.bump(fees) else {
+            // Nothing can replace the last broadcast; it gets another round.
+            println!("⛽ Nonce {} still pending at the {} wei fee cap", nonce, policy.max_fee_cap);
+            sent_at_block = Some(block);
+            continue;
+        };
+        if !cancelling && is_obsolete().await {
+            cancelling = true;
+            tx = cancellation(from, nonce, &tx);
+            println!("🛑 Cancelling nonce {} with a self-transfer: transaction no longer needed", nonce);
+        }
+        fees = bumped;
+        fees.apply(&mut tx);
+        match broadcast(client, &tx).await {
+            Ok(hash) => {
+                println!(
+                    "⛽ Replaced stuck nonce {} after {} block(s): max fee {} wei, tip {} wei",
+                    nonce,
+                    block - sent_at,
+                    fees.max_fee_per_gas,
+                    fees.max_priority_fee_per_gas
+                );
+                on_broadcast(hash);
+                broadcasts.push((hash, cancelling));
+                sent_at_block = Some(block);
+            }
+            // Usually "nonce too low" because a broadcast was just mined; the
+            // next pass picks up its receipt.
+            Err(e) => eprintln!("[ERROR] Failed to replace transaction with nonce {}: {}", nonce, e),
+        }
+    }
+}
+
+/// The outcome once a broadcast is mined or the nonce is used elsewhere.
+async fn poll<M: Middleware>(
+    client: &M,
+    from: Address,
+    nonce: U256,
+    broadcasts: &[(H256, bool)],
+) -> Result<Option<TxOutcome>> {
+    // Read the nonce before the receipts, so a receipt mined in between
+    // is still found on this pass rather than reported as foreign.
+    let mined_nonce = client
+        .get_transaction_count(from, None)
+        .await
+        .map_err(|e| anyhow!("{}", e))?;
+    for (hash, is_cancellation) in broadcasts {
+        let receipt = client
+            .get_transaction_receipt(*hash)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        if let Some(receipt) = receipt {
+            return Ok(Some(if *is_cancellation {
+                TxOutcome::Cancelled(receipt)
+            } else {
+                TxOutcome::Mined(receipt)
+            }));
+        }
+    }
+    if mined_nonce > nonce {
+        return Ok(Some(TxOutcome::NonceConsumed));
+    }
+    Ok(None)
+}
+
+async fn broadcast<M: Middleware>(client: &M, tx: &TypedTransaction) -> Result<H256> {
+    let pending = client
+        .send_transaction(tx.clone(), None)
+        .await
+        .map_err(|e| anyhow!("{}", e))?;
+    Ok(*pending)
+}
+
+async fn block_number<M: Middleware>(client: &M) -> Result<u64> {
+    Ok(client
+        .get_block_number()
+        .await
+        .map_err(|e| anyhow!("{}", e))?
+        .as_u64())
+}
+
+/// A zero-value transfer to self that takes over `nonce`.
+fn cancellation(from: Address, nonce: U256, original: &TypedTransaction) -> TypedTransaction {
+    let mut tx: TypedTransaction = match original {
+        TypedTransaction::Eip1559(_) => Eip1559TransactionRequest::new().into(),
+        _ => TransactionRequest::new().into(),
+    };
+    tx.set_from(from)
+        .set_to(from)
+        .set_value(U256::zero())
+        .set_nonce(nonce)
+        .set_gas(CANCEL_GAS);
+    if let Some(chain_id) = original.chain_id() {
+        tx.set_chain_id(chain_id);
+    }
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::Urgency;
+    use ethers::providers::{JsonRpcError, MockResponse};
+
+    fn policy() -> FeePolicy {
+        FeePolicy {
+            max_fee_cap: U256::from(1_000),
+            priority_fee_low: U256::one(),
+            priority_fee_normal: U256::one(),
+            priority_fee_high: U256::one(),
+            default_urgency: Urgency::Normal,
+            base_fee_multiplier: 2,
+            bump_percent: 10,
+            replace_after_blocks: 3,
+            max_replacements: 1,
+        }
+    }
+
+    fn rpc_error() -> MockResponse {
+        MockResponse::Error(JsonRpcError { code: -32000, message: "header not found".to_string(), data: None })
+    }
+
+    fn transaction() -> TypedTransaction {
+        TransactionRequest::new()
+            .from(Address::repeat_byte(0x11))
+            .to(Address::repeat_byte(0x22))
+            .nonce(7)
+            .gas(21_000)
+            .into()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rpc_errors_while_watching_are_retried() {
+        let (provider, mock) = Provider::mocked();
+        let hash = H256::repeat_byte(0xaa);
+        // Responses are served last in, first out.
+        mock.push(TransactionReceipt { transaction_hash: hash, ..Default::default() }).unwrap();
+        mock.push(U256::from(7)).unwrap(); // eth_getTransactionCount
+        mock.push_response(rpc_error()); // eth_getTransactionReceipt
+        mock.push(U256::from(7)).unwrap(); // eth_getTransactionCount
+        mock.push_response(rpc_error()); // eth_getTransactionCount
+        mock.push_response(rpc_error()); // eth_blockNumber
+        mock.push(hash).unwrap(); // eth_sendTransaction
+        mock.push(U256::from(5)).unwrap(); // eth_gasPrice
+        mock.push(Option::<Block<H256>>::None).unwrap(); // eth_getBlockByNumber: no base fee
+
+        let mut sent = Vec::new();
+        let outcome = send_tracked(&provider, transaction(), &policy(), Urgency::Normal, || async { false }, |hash| {
+            sent.push(hash)
+        })
+        .await
+        .unwrap();
+        assert!(matches!(outcome, TxOutcome::Mined(receipt) if receipt.transaction_hash == hash));
+        assert_eq!(sent, vec![hash]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_when_the_fee_cap_blocks_every_bump() {
+        let (provider, mock) = Provider::mocked();
+        let hash = H256::repeat_byte(0xaa);
+        // Responses are served last in, first out.
+        mock.push(U64::from(106)).unwrap(); // eth_blockNumber: second round is due
+        mock.push(Option::<TransactionReceipt>::None).unwrap();
+        mock.push(U256::from(7)).unwrap(); // eth_getTransactionCount
+        mock.push(U64::from(103)).unwrap(); // eth_blockNumber: first round is due
+        mock.push(Option::<TransactionReceipt>::None).unwrap();
+        mock.push(U256::from(7)).unwrap(); // eth_getTransactionCount
+        mock.push(U64::from(100)).unwrap(); // eth_blockNumber
+        mock.push(hash).unwrap(); // eth_sendTransaction
+        mock.push(U256::from(1_000)).unwrap(); // eth_gasPrice, already at the cap
+        mock.push(Option::<Block<H256>>::None).unwrap(); // eth_getBlockByNumber: no base fee
+
+        let mut sent = Vec::new();
+        let result = send_tracked(&provider, transaction(), &policy(), Urgency::Normal, || async { false }, |hash| {
+            sent.push(hash)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(sent, vec![hash]);
+        // Every queued response was used.
+        assert!(provider.get_block_number().await.is_err());
+    }
+}
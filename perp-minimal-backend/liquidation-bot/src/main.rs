@@ -2,12 +2,30 @@ use ethers::{
     abi::AbiDecode,
     prelude::*,
     providers::{Http, Provider},
-    signers::{LocalWallet, Signer},
+    signers::LocalWallet,
+    utils::{format_ether, parse_ether},
 };
-// NEW: Import HashMap for our new state management
-use std::{collections::HashMap, env, str::FromStr, sync::Arc, time::Duration};
+use std::{collections::HashSet, env, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 use tokio::sync::{Mutex, Semaphore};
 use anyhow::Result;
+use futures::stream::{self, StreamExt};
+
+mod multicall;
+mod nonce_manager;
+mod position_book;
+mod profitability;
+mod risk;
+mod store;
+mod wallet_pool;
+use keeper_tx::{
+    fees::{FeePolicy, Urgency},
+    tx_tracker::{self, TxOutcome},
+};
+use position_book::PositionBook;
+use profitability::{Decision, ProfitPolicy};
+use risk::{ProtocolConstants, TrackedPosition};
+use store::{AttemptStatus, LiquidationAttempt, Store};
+use wallet_pool::{Wallet, WalletPool};
 
 abigen!(
     ClearingHouseV2, "abi/ClearingHouseV2.json";
@@ -16,15 +34,57 @@ abigen!(
 
 struct Config {
     is_local_net: bool,
+    /// First block scanned when rebuilding the position set at startup.
+    deployment_block: u64,
+    log_chunk_size: u64,
+    /// Multicall3 used to batch `calculatePnl` checks; `None` if not deployed.
+    multicall_address: Option<Address>,
+    multicall_batch_size: usize,
 }
 
 struct AppState {
     config: Config,
-    active_positions: Mutex<HashMap<[u8; 32], Address>>,
-    nonce_manager: Mutex<U256>,
+    active_positions: Mutex<PositionBook>,
+    wallets: WalletPool,
+    store: Store,
+    profit_policy: ProfitPolicy,
+    fee_policy: FeePolicy,
+    /// Positions from dispatch until their liquidation is settled.
+    tracking: std::sync::Mutex<HashSet<[u8; 32]>>,
+    /// Bounds the liquidations running at once, across price updates.
+    send_permits: Semaphore,
+}
+
+/// Holds a position in `AppState::tracking` from the moment its liquidation
+/// is dispatched until it is settled. Price updates keep running while
+/// liquidations are watched, so this is what stops the next update from
+/// sending the same one again and `reconcile_pending_attempts` from settling
+/// it twice.
+struct Tracking {
+    state: Arc<AppState>,
+    position_id: [u8; 32],
+}
+
+impl Tracking {
+    /// `None` if the position is already being liquidated.
+    fn start(state: &Arc<AppState>, position_id: [u8; 32]) -> Option<Self> {
+        let started = state.tracking.lock().unwrap_or_else(|e| e.into_inner()).insert(position_id);
+        started.then(|| Self { state: Arc::clone(state), position_id })
+    }
+}
+
+impl Drop for Tracking {
+    fn drop(&mut self) {
+        self.state.tracking.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.position_id);
+    }
 }
 
 const MAX_CONCURRENT_RPC_CALLS: usize = 5;
+const DEFAULT_LOG_CHUNK_SIZE: u64 = 2_000;
+const POSITION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_MULTICALL_BATCH_SIZE: usize = 100;
+/// Wallet maintenance cycles between two statistics reports.
+const WALLET_REPORT_EVERY: u32 = 5;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -42,179 +102,625 @@ async fn main() -> Result<()> {
 
     dotenv::dotenv().ok();
     let rpc_url = env::var("RPC_URL").expect("RPC_URL must be set");
-    let private_key = env::var("LIQUIDATOR_PRIVATE_KEY").expect("LIQUIDATOR_PRIVATE_KEY must be set");
+    // Comma-separated; a single LIQUIDATOR_PRIVATE_KEY still works.
+    let private_keys = env::var("LIQUIDATOR_PRIVATE_KEYS").or_else(|_| env::var("LIQUIDATOR_PRIVATE_KEY")).expect("LIQUIDATOR_PRIVATE_KEYS or LIQUIDATOR_PRIVATE_KEY must be set");
     let clearing_house_address_str = env::var("CLEARING_HOUSE_CONTRACT_ADDRESS").expect("CLEARING_HOUSE_CONTRACT_ADDRESS must be set");
     let oracle_address_str = env::var("ORACLE_CONTRACT_ADDRESS").expect("ORACLE_CONTRACT_ADDRESS must be set");
+    let deployment_block: u64 = env::var("DEPLOYMENT_BLOCK").ok().map(|v| v.parse()).transpose()?.unwrap_or(0);
+    let log_chunk_size: u64 = env::var("LOG_CHUNK_SIZE").ok().map(|v| v.parse()).transpose()?.unwrap_or(DEFAULT_LOG_CHUNK_SIZE).max(1);
+    let state_db_path = env::var("STATE_DB_PATH").unwrap_or_else(|_| "./liquidation-bot-db".to_string());
+    let multicall_address: Address = env::var("MULTICALL_ADDRESS").ok().map(|v| v.parse()).transpose()?.unwrap_or(MULTICALL_ADDRESS);
+    let profit_policy = ProfitPolicy {
+        collateral_per_native: env::var("COLLATERAL_PER_NATIVE").ok().map(|v| U256::from_dec_str(&v)).transpose()?,
+        min_profit: env::var("MIN_LIQUIDATION_PROFIT").ok().map(|v| U256::from_dec_str(&v)).transpose()?.unwrap_or_default(),
+        force_liquidate: profitability::parse_position_ids(&env::var("FORCE_LIQUIDATE_POSITIONS").unwrap_or_default())?,
+    };
+    let fee_policy = FeePolicy::from_env()?;
+    let multicall_batch_size: usize = env::var("MULTICALL_BATCH_SIZE").ok().map(|v| v.parse()).transpose()?.unwrap_or(DEFAULT_MULTICALL_BATCH_SIZE).max(1);
+    let min_wallet_balance: U256 = parse_ether(env::var("MIN_WALLET_BALANCE_ETH").unwrap_or_else(|_| "0.05".to_string()))?;
 
     let provider = Provider::<Http>::try_from(&rpc_url)?;
     let chain_id = provider.get_chainid().await?.as_u64();
-    let wallet = LocalWallet::from_str(&private_key)?.with_chain_id(chain_id);
-    let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone())); // Clone provider for resync
-
     let clearing_house_address: Address = clearing_house_address_str.parse()?;
-    let clearing_house = ClearingHouseV2::new(clearing_house_address, Arc::clone(&client));
-    
+    let wallets = WalletPool::connect(&provider, chain_id, clearing_house_address, &private_keys, min_wallet_balance).await?;
+    // Reads and event streams go through the first wallet.
+    let clearing_house = wallets.primary().clearing_house.clone();
+    let client = clearing_house.client();
+
     let oracle_address: Address = oracle_address_str.parse()?;
     let oracle = Oracle::new(oracle_address, Arc::clone(&client));
 
-    let initial_nonce = client.get_transaction_count(wallet.address(), None).await?;
+    let constants = ProtocolConstants {
+        price_precision: clearing_house.price_precision().call().await?,
+        bps_divisor: clearing_house.bps_divisor().call().await?,
+        maintenance_margin_ratio_bps: clearing_house.maintenance_margin_ratio_bps().call().await?,
+        liquidation_fee_bps: clearing_house.liquidation_fee_bps().call().await?,
+    };
+
+    let multicall_address = multicall::detect(client.as_ref(), multicall_address).await;
+    match multicall_address {
+        Some(address) => println!("📦 Batching solvency checks through Multicall3 at {:#x}", address),
+        None => println!("📦 Multicall3 not deployed; solvency checks use individual calls."),
+    }
+
+    let store = Store::open(&state_db_path)?;
+    let mut book = PositionBook::new(constants);
+    book.extend(store.load_positions()?);
+    println!("💾 Loaded {} tracked position(s) from {}", book.len(), state_db_path);
 
     let app_state = Arc::new(AppState {
-        config: Config { is_local_net },
-        active_positions: Mutex::new(HashMap::new()),
-        nonce_manager: Mutex::new(initial_nonce),
+        config: Config { is_local_net, deployment_block, log_chunk_size, multicall_address, multicall_batch_size },
+        active_positions: Mutex::new(book),
+        wallets,
+        store,
+        profit_policy,
+        fee_policy,
+        tracking: Default::default(),
+        send_permits: Semaphore::new(MAX_CONCURRENT_RPC_CALLS),
     });
+    reconcile_pending_attempts(&app_state, client.as_ref()).await;
 
     println!("✅ V2 Liquidation Bot Started");
-    println!("-> Liquidator Account: {:#x}", client.address());
-    println!("-> Initial Nonce: {}", initial_nonce);
+    for wallet in app_state.wallets.wallets() {
+        println!("-> Liquidator Account: {:#x} (nonce {}, balance {} ETH)", wallet.address(), wallet.nonce_manager.next(), format_ether(wallet.balance()));
+    }
+    println!("-> Wallet balance floor: {} ETH", format_ether(app_state.wallets.min_balance));
+    if !app_state.profit_policy.is_enabled() {
+        println!("-> Profitability gating disabled (COLLATERAL_PER_NATIVE not set)");
+    }
     
     // --- Event Listening ---
     let position_listener_handle = tokio::spawn(listen_for_position_changes(Arc::clone(&app_state), clearing_house.clone()));
     let liquidation_trigger_handle = tokio::spawn(listen_for_price_changes(Arc::clone(&app_state), clearing_house.clone(), oracle.clone()));
     
     
-    let nonce_resync_handle = tokio::spawn(resync_nonce(Arc::clone(&app_state), provider, wallet.address()));
+    let wallet_maintenance_handle = tokio::spawn(maintain_wallets(Arc::clone(&app_state)));
     
-    tokio::try_join!(position_listener_handle, liquidation_trigger_handle, nonce_resync_handle)?;
+    let (positions, prices, wallets) = tokio::try_join!(position_listener_handle, liquidation_trigger_handle, wallet_maintenance_handle)?;
+    positions?;
+    prices?;
+    wallets?;
     Ok(())
 }
 
 
-/// V2: The core logic now iterates over position IDs
+/// Selects positions that are insolvent at `price` using local math, then
+/// confirms each candidate with `calculatePnl` and simulates its liquidation
+/// before sending it.
 async fn check_and_liquidate_positions(
     state: Arc<AppState>,
     clearing_house: ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    price: U256,
 ) {
-    let positions_to_check: Vec<[u8; 32]> = state.active_positions.lock().await.keys().cloned().collect();
+    reconcile_pending_attempts(&state, clearing_house.client().as_ref()).await;
+    let (tracked, positions_to_check) = {
+        let positions = state.active_positions.lock().await;
+        let candidates: Vec<[u8; 32]> = positions
+            .insolvent_at(price)
+            .into_iter()
+            .filter(|position_id| !is_in_flight(&state, position_id))
+            .collect();
+        (positions.len(), candidates)
+    };
     if positions_to_check.is_empty() { return; }
-    println!("Checking {} active position(s)...", positions_to_check.len());
+    println!("Confirming {} liquidation candidate(s) out of {} tracked position(s)...", positions_to_check.len(), tracked);
+
+    let confirmed = multicall::confirm_insolvent(
+        &clearing_house,
+        state.config.multicall_address,
+        state.config.multicall_batch_size,
+        positions_to_check,
+    )
+    .await;
+    let confirmed = simulate_liquidations(&state, &clearing_house, confirmed).await;
+    let confirmed = select_profitable(&state, &clearing_house, price, confirmed).await;
 
     // --- Conditional Logic ---
     if state.config.is_local_net {
         // --- Sequential execution for local Hardhat node ---
-        for position_id in positions_to_check {
-            let pnl_result = clearing_house.calculate_pnl(position_id).call().await;
-            if let Ok((_pnl, is_solvent)) = pnl_result {
-                if !is_solvent {
-                    println!("🔥 [SEQUENTIAL] Position ID {:?} is INSOLVENT! Attempting liquidation...", hex::encode(position_id));
-                    // For local automine, we don't need the complex nonce manager. 
-                    // The SignerMiddleware handles it correctly for sequential calls.
-                    let tx = clearing_house.liquidate(position_id);
-                    // We wait for each one to complete before starting the next.
-                    match tx.send().await {
-                        Ok(pending) => {
-                            let _ = pending.await; // Wait for confirmation
-                            println!("✅ [SEQUENTIAL] Liquidation tx for {:?} confirmed or failed.", hex::encode(position_id));
-                        },
-                        Err(e) => eprintln!("[ERROR] [SEQUENTIAL] Failed to send tx for {:?}: {}", hex::encode(position_id), e)
-                    };
-                };
-            }
+        for position_id in confirmed {
+            println!("🔥 [SEQUENTIAL] Position ID {:?} is INSOLVENT! Attempting liquidation...", hex::encode(position_id));
+            // For local automine, we don't need the complex nonce manager. 
+            // The SignerMiddleware handles it correctly for sequential calls.
+            // We wait for each one to complete before starting the next.
+            let Some(_tracking) = Tracking::start(&state, position_id) else { continue };
+            let Some(wallet) = assign_wallet(&state, position_id) else { continue };
+            liquidate_tracked(&state, &wallet, position_id, None).await;
+            println!("✅ [SEQUENTIAL] Liquidation tx for {:?} confirmed or failed.", hex::encode(position_id));
         }
     } else {
         // --- Concurrent execution for public networks ---
-        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_RPC_CALLS));
-        let mut tasks = Vec::new();
-
-        for position_id in positions_to_check {
-            let clearing_house_clone = clearing_house.clone();
-            let semaphore_clone = Arc::clone(&semaphore);
-            let state_clone = Arc::clone(&state);
-
-            tasks.push(tokio::spawn(async move {
-                let _permit = semaphore_clone.acquire().await.unwrap();
-                let pnl_result = clearing_house_clone.calculate_pnl(position_id).call().await;
-                if let Ok((_pnl, is_solvent)) = pnl_result {
-                    if !is_solvent {
-                        println!("🔥 [CONCURRENT] Position ID {:?} is INSOLVENT! Attempting liquidation...", hex::encode(position_id));
-                        send_liquidation_tx(state_clone, clearing_house_clone, position_id).await;
-                    }
-                }
-            }));
+        // Spawned rather than awaited: a liquidation is watched until mined,
+        // and the next price update must not wait for it.
+        for position_id in confirmed {
+            let Some(tracking) = Tracking::start(&state, position_id) else { continue };
+            tokio::spawn(send_liquidation_tx(tracking));
         }
-        futures::future::join_all(tasks).await;
     }
     println!("✅ Finished checking all positions.");
 }
 
-async fn send_liquidation_tx(
-    state: Arc<AppState>,
-    clearing_house: ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
-    position_id: [u8; 32]
-) {
-    let nonce_to_use = {
-        let mut nonce_lock = state.nonce_manager.lock().await;
-        let nonce = *nonce_lock;
-        *nonce_lock += U256::one();
-        nonce
-    };
+/// Simulates each `liquidate` with an `eth_call` against the pending block
+/// and drops the ones that would revert, recording the decoded reason. A
+/// pending block that already holds another keeper's liquidation shows up
+/// as `PositionNotFound`. Candidates whose simulation could not run at all
+/// are kept; sending surfaces the same failure.
+async fn simulate_liquidations(
+    state: &AppState,
+    clearing_house: &ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    candidates: Vec<[u8; 32]>,
+) -> Vec<[u8; 32]> {
+    let results: Vec<_> = stream::iter(candidates)
+        .map(|position_id| async move {
+            let result = clearing_house.liquidate(position_id).block(BlockNumber::Pending).call().await;
+            (position_id, result)
+        })
+        .buffer_unordered(MAX_CONCURRENT_RPC_CALLS)
+        .collect()
+        .await;
 
-    let mut tx = clearing_house.liquidate(position_id);
-    tx.tx.set_nonce(nonce_to_use);
-    
-    match tx.send().await {
-        Ok(pending_tx) => {
-            if let Ok(Some(receipt)) = pending_tx.await {
-                println!("✅ SUCCESS: Liquidated {:?}. Tx: {:#x}", hex::encode(position_id), receipt.transaction_hash);
+    let mut passed = Vec::new();
+    for (position_id, result) in results {
+        match result {
+            Ok(()) => passed.push(position_id),
+            Err(ContractError::Revert(data)) => {
+                if PositionNotFound::decode(&data).is_ok() {
+                    println!("⏭️ Skipping {:?}: already liquidated or closed (pending block)", hex::encode(position_id));
+                } else {
+                    println!("⏭️ Skipping {:?}: liquidation would revert", hex::encode(position_id));
+                }
+                let reason = decode_contract_error(ContractError::Revert(data));
+                record_attempt(state, position_id, LiquidationAttempt::skipped(reason));
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Simulation failed for {:?}, sending anyway: {}", hex::encode(position_id), e);
+                passed.push(position_id);
+            }
+        }
+    }
+    passed
+}
+
+/// Drops liquidations whose reward would not cover gas plus the minimum
+/// profit, except for positions on the override list. Without current fees
+/// the check cannot run, so only the override list goes ahead and the rest
+/// wait for the next price update. Every dropped position gets a skipped or
+/// deferred attempt with the reason.
+async fn select_profitable(
+    state: &AppState,
+    clearing_house: &ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    price: U256,
+    confirmed: Vec<[u8; 32]>,
+) -> Vec<[u8; 32]> {
+    let policy = &state.profit_policy;
+    if !policy.is_enabled() || confirmed.is_empty() {
+        return confirmed;
+    }
+    // Priced at the max fee the send will offer: the cost is then never more
+    // than estimated, whatever the base fee does before inclusion.
+    let fees = state.fee_policy.fees(clearing_house.client().as_ref(), state.fee_policy.default_urgency).await;
+    let gas_price = match fees {
+        Ok(fees) => fees.max_fee_per_gas,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to fetch fees, deferring all but forced liquidations: {}", e);
+            let error = format!("fees unavailable for the profit check: {}", e);
+            let (forced, deferred): (Vec<_>, Vec<_>) =
+                confirmed.into_iter().partition(|position_id| policy.force_liquidate.contains(position_id));
+            for position_id in deferred {
+                record_attempt(state, position_id, LiquidationAttempt::deferred(error.clone()));
+            }
+            return forced;
+        }
+    };
+    let mut selected = Vec::new();
+    for position_id in confirmed {
+        let Some(reward) = state.active_positions.lock().await.liquidation_reward(&position_id, price) else { continue };
+        let gas = match clearing_house.liquidate(position_id).estimate_gas().await {
+            Ok(gas) => gas,
+            Err(e) => {
+                let error = format!("gas estimate failed: {}", decode_contract_error(e));
+                eprintln!("[ERROR] Skipping {:?}: {}", hex::encode(position_id), error);
+                record_attempt(state, position_id, LiquidationAttempt::skipped(error));
+                continue;
+            }
+        };
+        match policy.decide(&position_id, reward, gas, gas_price) {
+            Decision::Liquidate => selected.push(position_id),
+            Decision::Forced => {
+                println!("⚠️ Liquidating {:?} regardless of cost (override list)", hex::encode(position_id));
+                selected.push(position_id);
+            }
+            Decision::Defer { reward, cost } => {
+                println!("⏸️ Deferring {:?}: reward {} vs gas cost {} (min profit {})", hex::encode(position_id), reward, cost, policy.min_profit);
+                let error = format!("unprofitable: reward {} vs gas cost {} (min profit {})", reward, cost, policy.min_profit);
+                record_attempt(state, position_id, LiquidationAttempt::deferred(error));
             }
+        }
+    }
+    selected
+}
+
+/// Picks the wallet for a liquidation, recording a failed attempt if every
+/// wallet is below the balance floor.
+fn assign_wallet(state: &AppState, position_id: [u8; 32]) -> Option<wallet_pool::Assignment> {
+    let wallet = state.wallets.assign();
+    if wallet.is_none() {
+        let error = format!("every liquidator wallet is below the {} ETH balance floor", format_ether(state.wallets.min_balance));
+        eprintln!("[ERROR] Skipping liquidation of {:?}: {}", hex::encode(position_id), error);
+        record_attempt(state, position_id, LiquidationAttempt::failed(error));
+    }
+    wallet
+}
+
+async fn send_liquidation_tx(tracking: Tracking) {
+    let (state, position_id) = (&tracking.state, tracking.position_id);
+    let Ok(_permit) = state.send_permits.acquire().await else { return };
+    println!("🔥 [CONCURRENT] Position ID {:?} is INSOLVENT! Attempting liquidation...", hex::encode(position_id));
+    let Some(wallet) = assign_wallet(state, position_id) else { return };
+    let nonce_to_use = wallet.nonce_manager.reserve();
+
+    liquidate_tracked(state, &wallet, position_id, Some(nonce_to_use)).await;
+}
+
+/// Sends a liquidation at policy fees and follows it until it is mined,
+/// replacing it while stuck and cancelling it once the position is gone.
+/// Every broadcast and the final outcome are recorded in the store and the
+/// sending wallet's statistics. A `reserved` nonce from the wallet's nonce
+/// manager is settled with it as well. The caller holds the position's
+/// `Tracking` throughout.
+async fn liquidate_tracked(
+    state: &AppState,
+    wallet: &Wallet,
+    position_id: [u8; 32],
+    reserved: Option<U256>,
+) {
+    let clearing_house = &wallet.clearing_house;
+    let client = clearing_house.client();
+    let mut tx = clearing_house.liquidate(position_id).tx;
+    if let Some(nonce) = reserved {
+        tx.set_nonce(nonce);
+    }
+    // Fills the nonce (if unset), sender and gas limit; reverts surface here.
+    if let Err(e) = client.fill_transaction(&mut tx, None).await {
+        if let Some(nonce) = reserved {
+            wallet.nonce_manager.release(nonce);
+            wallet.nonce_manager.fill_gaps(client.as_ref()).await;
+        }
+        let decoded_error = decode_contract_error(ContractError::from_middleware_error(e));
+        eprintln!("[ERROR] Failed to send tx for {:?}: {}", hex::encode(position_id), decoded_error);
+        wallet.record_outcome(AttemptStatus::Failed, None);
+        record_attempt(state, position_id, LiquidationAttempt::failed(decoded_error));
+        return;
+    }
+    let nonce = tx.nonce().map(|n| n.as_u64());
+    let urgency = if state.profit_policy.force_liquidate.contains(&position_id) { Urgency::High } else { state.fee_policy.default_urgency };
+
+    let mut attempt: Option<LiquidationAttempt> = None;
+    let outcome = tx_tracker::send_tracked(
+        client.as_ref(),
+        tx,
+        &state.fee_policy,
+        urgency,
+        || position_gone(clearing_house, position_id),
+        |tx_hash| {
+            let next = match attempt.take() {
+                Some(previous) => previous.replaced_by(tx_hash),
+                None => {
+                    if let Some(nonce) = reserved {
+                        wallet.nonce_manager.confirm(nonce);
+                    }
+                    wallet.record_sent();
+                    LiquidationAttempt::pending(tx_hash, nonce, wallet.address())
+                }
+            };
+            record_attempt(state, position_id, next.clone());
+            attempt = Some(next);
         },
+    )
+    .await;
+
+    let Some(mut attempt) = attempt else {
+        let error = outcome.err().map(|e| e.to_string()).unwrap_or_default();
+        eprintln!("[ERROR] Failed to send tx for {:?}: {}", hex::encode(position_id), error);
+        if let Some(nonce) = reserved {
+            wallet.nonce_manager.handle_send_error(client.as_ref(), nonce, &error).await;
+        }
+        wallet.record_outcome(AttemptStatus::Failed, None);
+        record_attempt(state, position_id, LiquidationAttempt::failed(error));
+        return;
+    };
+    let receipt = match outcome {
+        Ok(TxOutcome::Mined(receipt)) => {
+            println!("✅ SUCCESS: Liquidated {:?}. Tx: {:#x}", hex::encode(position_id), receipt.transaction_hash);
+            attempt.settle(&receipt);
+            Some(receipt)
+        }
+        Ok(TxOutcome::Cancelled(receipt)) => {
+            println!("🛑 Cancelled liquidation of {:?}: position already closed or liquidated", hex::encode(position_id));
+            attempt.settle(&receipt);
+            Some(receipt)
+        }
+        Ok(TxOutcome::NonceConsumed) => {
+            attempt.status = AttemptStatus::Failed;
+            attempt.error = Some("nonce used by another transaction".to_string());
+            if let Err(e) = wallet.nonce_manager.sync(client.as_ref()).await {
+                eprintln!("[ERROR] Failed to resync nonce: {}", e);
+            }
+            None
+        }
+        // Left pending; reconciled from its receipts later.
+        Err(e) => {
+            eprintln!("[ERROR] Lost track of liquidation tx for {:?}: {}", hex::encode(position_id), e);
+            return;
+        }
+    };
+    wallet.record_outcome(attempt.status, receipt.as_ref());
+    if let Err(e) = wallet.refresh_balance().await {
+        eprintln!("[ERROR] Failed to refresh balance of {:#x}: {}", wallet.address(), e);
+    }
+    record_attempt(state, position_id, attempt);
+}
+
+/// True once the position no longer exists on chain, e.g. because someone
+/// else liquidated it first.
+async fn position_gone(
+    clearing_house: &ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    position_id: [u8; 32],
+) -> bool {
+    matches!(clearing_house.positions(position_id).call().await, Ok((owner, ..)) if owner == Address::zero())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+impl LiquidationAttempt {
+    fn pending(tx_hash: H256, nonce: Option<u64>, sender: Address) -> Self {
+        Self { tx_hash: Some(tx_hash), replaced_tx_hashes: Vec::new(), nonce, status: AttemptStatus::Pending, submitted_at: unix_now(), block_number: None, error: None, sender: Some(sender) }
+    }
+
+    fn failed(error: String) -> Self {
+        Self { tx_hash: None, replaced_tx_hashes: Vec::new(), nonce: None, status: AttemptStatus::Failed, submitted_at: unix_now(), block_number: None, error: Some(error), sender: None }
+    }
+
+    fn skipped(error: String) -> Self {
+        Self { status: AttemptStatus::Skipped, ..Self::failed(error) }
+    }
+
+    fn deferred(error: String) -> Self {
+        Self { status: AttemptStatus::Deferred, ..Self::failed(error) }
+    }
+
+    fn replaced_by(mut self, tx_hash: H256) -> Self {
+        self.replaced_tx_hashes.extend(self.tx_hash.replace(tx_hash));
+        self
+    }
+
+    /// Every broadcast that may still be mined, newest first.
+    fn tx_hashes(&self) -> impl Iterator<Item = H256> + '_ {
+        self.tx_hash.into_iter().chain(self.replaced_tx_hashes.iter().rev().copied())
+    }
+
+    /// Settles a pending attempt from the receipt of one of its broadcasts.
+    /// A mined self-transfer means the liquidation was cancelled.
+    fn settle(&mut self, receipt: &TransactionReceipt) {
+        self.block_number = receipt.block_number.map(|b| b.as_u64());
+        if receipt.to == Some(receipt.from) {
+            self.status = AttemptStatus::Failed;
+            self.error = Some("cancelled: position already closed or liquidated".to_string());
+        } else if receipt.status == Some(U64::one()) {
+            self.status = AttemptStatus::Confirmed;
+        } else {
+            self.status = AttemptStatus::Reverted;
+        }
+    }
+}
+
+fn is_tracked(state: &AppState, position_id: &[u8; 32]) -> bool {
+    state.tracking.lock().unwrap_or_else(|e| e.into_inner()).contains(position_id)
+}
+
+fn is_in_flight(state: &AppState, position_id: &[u8; 32]) -> bool {
+    is_tracked(state, position_id)
+        || matches!(state.store.get_attempt(position_id), Ok(Some(attempt)) if attempt.status == AttemptStatus::Pending)
+}
+
+fn record_attempt(state: &AppState, position_id: [u8; 32], attempt: LiquidationAttempt) {
+    if let Err(e) = state.store.record_attempt(position_id, &attempt) {
+        eprintln!("[ERROR] Failed to persist liquidation attempt for {:?}: {}", hex::encode(position_id), e);
+    }
+}
+
+/// Resolves attempts left pending by a restart or a lost receipt, crediting
+/// the outcome to the wallet that sent them. Attempts still watched by
+/// `liquidate_tracked` or with a broadcast known to the node stay in flight.
+async fn reconcile_pending_attempts<M: Middleware>(state: &AppState, client: &M) {
+    let pending = match state.store.pending_attempts() {
+        Ok(pending) => pending,
         Err(e) => {
-            let decoded_error = decode_contract_error(e);
-            eprintln!("[ERROR] Failed to send tx for {:?}: {}", hex::encode(position_id), decoded_error);
+            eprintln!("[ERROR] Failed to read pending liquidation attempts: {}", e);
+            return;
         }
     };
+    'attempts: for (position_id, mut attempt) in pending {
+        if is_tracked(state, &position_id) {
+            continue;
+        }
+        let tx_hashes: Vec<H256> = attempt.tx_hashes().collect();
+        if tx_hashes.is_empty() { continue; }
+        let mut receipt = None;
+        for tx_hash in &tx_hashes {
+            match client.get_transaction_receipt(*tx_hash).await {
+                Ok(Some(found)) => {
+                    receipt = Some(found);
+                    break;
+                }
+                Ok(None) => {}
+                Err(_) => continue 'attempts,
+            }
+        }
+        match &receipt {
+            Some(receipt) => attempt.settle(receipt),
+            None => {
+                for tx_hash in &tx_hashes {
+                    match client.get_transaction(*tx_hash).await {
+                        Ok(Some(_)) | Err(_) => continue 'attempts,
+                        Ok(None) => {}
+                    }
+                }
+                attempt.status = AttemptStatus::Failed;
+                attempt.error = Some("dropped from mempool".to_string());
+            }
+        }
+        println!("🔁 Liquidation attempt for {:?} settled as {:?}", hex::encode(position_id), attempt.status);
+        // Attempts stored before senders were recorded, or sent by a wallet
+        // no longer configured, only update the store.
+        if let Some(wallet) = attempt.sender.and_then(|sender| state.wallets.get(sender)) {
+            wallet.record_outcome(attempt.status, receipt.as_ref());
+        }
+        record_attempt(state, position_id, attempt);
+    }
 }
 
-async fn resync_nonce(state: Arc<AppState>, provider: Provider<Http>, wallet_address: Address) -> Result<()> {
-    // This is less critical for local mode but good to keep for production
-    if !state.config.is_local_net {
-        loop {
-            tokio::time::sleep(Duration::from_secs(60)).await;
-            if let Ok(on_chain_nonce) = provider.get_transaction_count(wallet_address, None).await {
-                let mut nonce_lock = state.nonce_manager.lock().await;
-                if *nonce_lock != on_chain_nonce {
-                    println!("[RESYNC] Nonce out of sync! Local: {}, On-chain: {}. Correcting.", *nonce_lock, on_chain_nonce);
-                    *nonce_lock = on_chain_nonce;
+/// Refreshes every wallet's balance against the floor and, outside local
+/// mode, resyncs its nonce. Statistics are reported every few cycles.
+async fn maintain_wallets(state: Arc<AppState>) -> Result<()> {
+    let mut cycles: u32 = 0;
+    loop {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        for wallet in state.wallets.wallets() {
+            // This is less critical for local mode but good to keep for production
+            if !state.config.is_local_net {
+                if let Err(e) = wallet.nonce_manager.sync(wallet.clearing_house.client().as_ref()).await {
+                    eprintln!("[ERROR] Failed to resync nonce of {:#x}: {}", wallet.address(), e);
+                }
+            }
+            match wallet.refresh_balance().await {
+                Ok(balance) if balance < state.wallets.min_balance => {
+                    println!("⚠️ Wallet {:#x} balance {} ETH is below the {} ETH floor; it gets no new liquidations until topped up.", wallet.address(), format_ether(balance), format_ether(state.wallets.min_balance));
                 }
+                Ok(_) => {}
+                Err(e) => eprintln!("[ERROR] Failed to refresh balance of {:#x}: {}", wallet.address(), e),
             }
         }
+        cycles += 1;
+        if cycles.is_multiple_of(WALLET_REPORT_EVERY) {
+            state.wallets.report();
+        }
     }
-    Ok(())
 }
 
 
 
-// V2: Updated to handle new event structures and store positionId->owner
-async fn listen_for_position_changes(
-    state: Arc<AppState>,
-    clearing_house: ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+/// Applies one position event to the tracked set and the store. Events at or
+/// before the store cursor are skipped, so the live stream can overlap the
+/// bootstrap range and a restart can re-scan the cursor's block.
+fn apply_position_event(
+    positions: &mut PositionBook,
+    store: &Store,
+    event: &ClearingHouseV2Events,
+    meta: &LogMeta,
+    verbose: bool,
 ) -> Result<()> {
-    println!("👂 Listening for V2 position management events...");
-    let events = clearing_house.events().from_block(BlockNumber::Latest);
-    let mut stream = events.stream().await?;
-    
-    while let Some(Ok(log)) = stream.next().await {
-        let mut positions = state.active_positions.lock().await;
-        match log {
-            ClearingHouseV2Events::PositionOpenedFilter(f) => {
-                positions.insert(f.position_id, f.user);
+    let log_position = (meta.block_number.as_u64(), meta.log_index.as_u64());
+    if store.cursor()?.is_some_and(|cursor| log_position <= cursor) {
+        return Ok(());
+    }
+    match event {
+        ClearingHouseV2Events::PositionOpenedFilter(f) => {
+            let position = TrackedPosition {
+                owner: f.user,
+                size: f.size,
+                margin: f.margin,
+                entry_price: f.entry_price,
+                is_long: f.is_long,
+            };
+            store.insert_position(f.position_id, &position)?;
+            positions.insert(f.position_id, position);
+            if verbose {
                 println!("➕ Added position: ID={:?}, Owner={}", hex::encode(f.position_id), f.user);
             }
-            ClearingHouseV2Events::PositionClosedFilter(f) => {
-                positions.remove(&f.position_id);
+        }
+        ClearingHouseV2Events::MarginAddedFilter(f) => {
+            if let Some(position) = positions.update_margin(&f.position_id, |margin| margin + f.amount) {
+                store.insert_position(f.position_id, position)?;
+            }
+        }
+        ClearingHouseV2Events::MarginRemovedFilter(f) => {
+            if let Some(position) = positions.update_margin(&f.position_id, |margin| margin.saturating_sub(f.amount)) {
+                store.insert_position(f.position_id, position)?;
+            }
+        }
+        ClearingHouseV2Events::PositionClosedFilter(f) => {
+            positions.remove(&f.position_id);
+            store.remove_position(&f.position_id)?;
+            if verbose {
                 println!("➖ Removed (closed) position: ID={:?}", hex::encode(f.position_id));
             }
-            ClearingHouseV2Events::PositionLiquidatedFilter(f) => {
-                positions.remove(&f.position_id);
+        }
+        ClearingHouseV2Events::PositionLiquidatedFilter(f) => {
+            positions.remove(&f.position_id);
+            store.remove_position(&f.position_id)?;
+            if verbose {
                 println!("➖ Removed (liquidated) position: ID={:?}", hex::encode(f.position_id));
             }
-            _ => {}
         }
+        _ => {}
     }
+    store.advance_cursor(log_position)?;
     Ok(())
 }
 
+/// Brings the position set up to `to_block` by replaying position events from
+/// the store cursor (or the deployment block on first run), in chunks the
+/// RPC node will accept.
+async fn sync_positions(
+    state: &AppState,
+    clearing_house: &ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    to_block: u64,
+    verbose: bool,
+) -> Result<()> {
+    let mut from_block = state.store.cursor()?.map_or(state.config.deployment_block, |(block, _)| block);
+    while from_block <= to_block {
+        let chunk_end = (from_block + state.config.log_chunk_size - 1).min(to_block);
+        let events = clearing_house.events().from_block(from_block).to_block(chunk_end).query_with_meta().await?;
+        let mut positions = state.active_positions.lock().await;
+        for (event, meta) in &events {
+            apply_position_event(&mut positions, &state.store, event, meta, verbose)?;
+        }
+        state.store.advance_cursor((chunk_end, u64::MAX))?;
+        from_block = chunk_end + 1;
+    }
+    Ok(())
+}
+
+// V2: Updated to handle new event structures and store positionId->owner
+/// Polls `eth_getLogs` from the store cursor rather than holding a log
+/// filter: the bootstrap can outlast the node's filter timeout, and an
+/// expired filter only surfaces as errors the filter stream swallows.
+async fn listen_for_position_changes(
+    state: Arc<AppState>,
+    clearing_house: ClearingHouseV2<SignerMiddleware<Provider<Http>, LocalWallet>>,
+) -> Result<()> {
+    let latest_block = clearing_house.client().get_block_number().await?.as_u64();
+    let from_block = state.store.cursor()?.map_or(state.config.deployment_block, |(block, _)| block);
+    println!("⏪ Replaying position events from block {} to {}...", from_block, latest_block);
+    sync_positions(&state, &clearing_house, latest_block, false).await?;
+    println!("✅ Bootstrap complete: tracking {} open position(s).", state.active_positions.lock().await.len());
+
+    println!("👂 Listening for V2 position management events...");
+    let mut interval = tokio::time::interval(POSITION_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let synced = async {
+            let head = clearing_house.client().get_block_number().await?.as_u64();
+            sync_positions(&state, &clearing_house, head, true).await
+        };
+        if let Err(e) = synced.await {
+            eprintln!("[ERROR] Failed to poll position events, retrying: {}", e);
+        }
+    }
+}
 
 fn decode_contract_error(e: ContractError<SignerMiddleware<Provider<Http>, LocalWallet>>) -> String {
     if let ContractError::Revert(data) = e {
@@ -235,9 +741,10 @@ async fn listen_for_price_changes(
     let events = oracle.events().from_block(BlockNumber::Latest);
     let mut stream = events.stream().await?;
 
-    while let Some(Ok(_)) = stream.next().await {
-        println!("\n🚨 Oracle price updated! Checking for liquidatable positions...");
-        check_and_liquidate_positions(Arc::clone(&state), clearing_house.clone()).await;
+    while let Some(Ok(event)) = stream.next().await {
+        let OracleEvents::PriceUpdatedFilter(update) = event else { continue };
+        println!("\n🚨 Oracle price updated to {}! Checking for liquidatable positions...", update.new_price);
+        check_and_liquidate_positions(Arc::clone(&state), clearing_house.clone(), update.new_price).await;
     }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+// src/multicall.rs
+//! Confirms liquidation candidates on chain with `calculatePnl`, batched
+//! through Multicall3 `aggregate3` when it is deployed and one call per
+//! position otherwise.
+use crate::{ClearingHouseV2, MAX_CONCURRENT_RPC_CALLS};
+use ethers::{
+    abi::{Token, Tokenizable},
+    contract::Multicall,
+    prelude::*,
+};
+use futures::stream::{self, StreamExt};
+
+/// Returns `address` if contract code is deployed there.
+pub async fn detect<M: Middleware>(client: &M, address: Address) -> Option<Address> {
+    match client.get_code(address, None).await {
+        Ok(code) if !code.is_empty() => Some(address),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to look up Multicall3 at {:#x}: {}", address, e);
+            None
+        }
+    }
+}
+
+/// Returns the candidates the contract reports as insolvent. Positions whose
+/// check failed (e.g. already closed) are left out.
+pub async fn confirm_insolvent<M: Middleware + 'static>(
+    clearing_house: &ClearingHouseV2<M>,
+    multicall_address: Option<Address>,
+    batch_size: usize,
+    candidates: Vec<[u8; 32]>,
+) -> Vec<[u8; 32]> {
+    let Some(multicall_address) = multicall_address else {
+        return confirm_individually(clearing_house, candidates).await;
+    };
+    let mut confirmed = Vec::new();
+    for batch in candidates.chunks(batch_size.max(1)) {
+        match confirm_batch(clearing_house, multicall_address, batch).await {
+            Ok(insolvent) => confirmed.extend(insolvent),
+            Err(e) => {
+                eprintln!("[ERROR] Multicall batch failed, falling back to single calls: {}", e);
+                confirmed.extend(confirm_individually(clearing_house, batch.to_vec()).await);
+            }
+        }
+    }
+    confirmed
+}
+
+async fn confirm_batch<M: Middleware + 'static>(
+    clearing_house: &ClearingHouseV2<M>,
+    multicall_address: Address,
+    batch: &[[u8; 32]],
+) -> Result<Vec<[u8; 32]>, MulticallError<M>> {
+    let mut multicall = Multicall::new(clearing_house.client(), Some(multicall_address)).await?;
+    for position_id in batch {
+        // A closed position reverts with PositionNotFound; that must not fail the batch.
+        multicall.add_call(clearing_house.calculate_pnl(*position_id), true);
+    }
+    let results = multicall.call_raw().await?;
+    Ok(batch
+        .iter()
+        .zip(results)
+        .filter(|(_, result)| matches!(result, Ok(token) if is_insolvent(token)))
+        .map(|(position_id, _)| *position_id)
+        .collect())
+}
+
+/// `calculatePnl` returns `(int256 pnl, bool isSolvent)`.
+fn is_insolvent(token: &Token) -> bool {
+    matches!(<(I256, bool)>::from_token(token.clone()), Ok((_, false)))
+}
+
+async fn confirm_individually<M: Middleware + 'static>(
+    clearing_house: &ClearingHouseV2<M>,
+    candidates: Vec<[u8; 32]>,
+) -> Vec<[u8; 32]> {
+    stream::iter(candidates)
+        .map(|position_id| async move {
+            match clearing_house.calculate_pnl(position_id).call().await {
+                Ok((_, false)) => Some(position_id),
+                _ => None,
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_RPC_CALLS)
+        .filter_map(|confirmed| async move { confirmed })
+        .collect()
+        .await
+}
@@ -0,0 +1,353 @@
+// src/nonce_manager.rs
+//! Hands out nonces for concurrent liquidations. A reservation that never
+//! reaches the mempool is released and reused by the next reservation. If
+//! higher nonces are already in use, a zero-value self-transfer fills the
+//! released one right away so they do not queue behind it. Errors showing
+//! the local view is stale trigger an immediate resync.
+use anyhow::{anyhow, Result};
+use ethers::prelude::*;
+use std::{
+    collections::BTreeSet,
+    sync::{Mutex, MutexGuard},
+};
+
+const GAP_FILL_GAS: u64 = 21_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The nonce is already mined.
+    NonceTooLow,
+    /// Another transaction with this nonce is pending at higher fees.
+    ReplacementUnderpriced,
+    /// The send failed for a reason unrelated to the nonce.
+    Other,
+}
+
+impl SendError {
+    pub fn classify(error: &str) -> Self {
+        let error = error.to_ascii_lowercase();
+        if error.contains("nonce too low")
+            || error.contains("nonce has already been used")
+            || error.contains("already known")
+        {
+            SendError::NonceTooLow
+        } else if error.contains("replacement transaction underpriced")
+            || error.contains("replacement underpriced")
+        {
+            SendError::ReplacementUnderpriced
+        } else {
+            SendError::Other
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Nonces {
+    /// Next nonce never handed out.
+    next: U256,
+    /// Reserved but not yet accepted by the node.
+    outstanding: BTreeSet<U256>,
+    /// Returned by failed sends; handed out again before `next`.
+    released: BTreeSet<U256>,
+}
+
+#[derive(Debug)]
+pub struct NonceManager {
+    address: Address,
+    nonces: Mutex<Nonces>,
+}
+
+impl NonceManager {
+    pub fn new(address: Address, next: U256) -> Self {
+        Self {
+            address,
+            nonces: Mutex::new(Nonces { next, ..Default::default() }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Nonces> {
+        self.nonces.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn next(&self) -> U256 {
+        self.lock().next
+    }
+
+    pub fn reserve(&self) -> U256 {
+        let mut nonces = self.lock();
+        let nonce = match nonces.released.pop_first() {
+            Some(nonce) => nonce,
+            None => {
+                let nonce = nonces.next;
+                nonces.next += U256::one();
+                nonce
+            }
+        };
+        nonces.outstanding.insert(nonce);
+        nonce
+    }
+
+    /// The node accepted a transaction with this nonce.
+    pub fn confirm(&self, nonce: U256) {
+        self.lock().outstanding.remove(&nonce);
+    }
+
+    /// The nonce never reached the node and can be handed out again.
+    pub fn release(&self, nonce: U256) {
+        let mut nonces = self.lock();
+        if nonces.outstanding.remove(&nonce) {
+            nonces.released.insert(nonce);
+        }
+    }
+
+    /// Takes the released nonces that a higher nonce, already handed out
+    /// and not released itself, would wait behind.
+    fn take_gaps(&self) -> Vec<U256> {
+        let mut nonces = self.lock();
+        // One past the highest nonce handed out and kept.
+        let mut used_below = nonces.next;
+        while !used_below.is_zero() && nonces.released.contains(&(used_below - 1)) {
+            used_below -= U256::one();
+        }
+        let gaps: Vec<U256> = nonces.released.range(..used_below).copied().collect();
+        for nonce in &gaps {
+            nonces.released.remove(nonce);
+        }
+        gaps
+    }
+
+    /// Fills released nonces that later transactions wait behind with
+    /// zero-value self-transfers. A fill the node rejects for a reason
+    /// other than the nonce leaves the nonce released for the next reservation.
+    pub async fn fill_gaps<M: Middleware>(&self, client: &M) {
+        for nonce in self.take_gaps() {
+            let tx = TransactionRequest::new()
+                .from(self.address)
+                .to(self.address)
+                .value(U256::zero())
+                .nonce(nonce)
+                .gas(GAP_FILL_GAS);
+            match client.send_transaction(tx, None).await {
+                Ok(pending) => println!("🧩 Filled nonce gap {} with self-transfer {:#x}", nonce, *pending),
+                Err(e) => {
+                    let error = e.to_string();
+                    eprintln!("[ERROR] Failed to fill nonce gap {}: {}", nonce, error);
+                    if SendError::classify(&error) == SendError::Other {
+                        self.lock().released.insert(nonce);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Settles a reservation whose send failed. Nonce errors mean the
+    /// local view is stale, so the account is resynced right away;
+    /// anything else leaves the nonce unused and releases it.
+    pub async fn handle_send_error<M: Middleware>(
+        &self,
+        client: &M,
+        nonce: U256,
+        error: &str,
+    ) -> SendError {
+        let kind = SendError::classify(error);
+        match kind {
+            SendError::NonceTooLow | SendError::ReplacementUnderpriced => {
+                self.confirm(nonce);
+                if let Err(e) = self.sync(client).await {
+                    eprintln!("[ERROR] Failed to resync nonce after send error: {}", e);
+                }
+            }
+            SendError::Other => {
+                self.release(nonce);
+                self.fill_gaps(client).await;
+            }
+        }
+        kind
+    }
+
+    /// Aligns with the account's pending transaction count. Released nonces
+    /// already used on chain are dropped; if transactions vanished from the
+    /// mempool, `next` falls back so their nonces are filled again.
+    pub async fn sync<M: Middleware>(&self, client: &M) -> Result<U256> {
+        let on_chain = client
+            .get_transaction_count(self.address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        let mut nonces = self.lock();
+        let floor = nonces
+            .outstanding
+            .last()
+            .map_or(on_chain, |highest| on_chain.max(*highest + 1));
+        if nonces.next != floor {
+            println!("[RESYNC] Nonce out of sync! Local: {}, On-chain: {}. Correcting.", nonces.next, on_chain);
+        }
+        nonces.next = floor;
+        nonces.released.retain(|nonce| *nonce >= on_chain && *nonce < floor);
+        Ok(floor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{JsonRpcError, MockResponse};
+
+    fn manager(next: u64) -> NonceManager {
+        NonceManager::new(Address::repeat_byte(0x11), U256::from(next))
+    }
+
+    #[test]
+    fn reserves_sequential_nonces() {
+        let nonces = manager(7);
+        assert_eq!(nonces.reserve(), U256::from(7));
+        assert_eq!(nonces.reserve(), U256::from(8));
+        assert_eq!(nonces.next(), U256::from(9));
+    }
+
+    #[test]
+    fn released_nonce_fills_the_gap_first() {
+        let nonces = manager(0);
+        let first = nonces.reserve();
+        let second = nonces.reserve();
+        nonces.confirm(second);
+        nonces.release(first);
+        assert_eq!(nonces.reserve(), first);
+        assert_eq!(nonces.reserve(), U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn released_nonce_below_a_used_one_is_filled_right_away() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(H256::repeat_byte(0xaa)).unwrap(); // eth_sendTransaction
+        mock.push(U256::from(1)).unwrap(); // eth_gasPrice
+        let nonces = manager(0);
+        let first = nonces.reserve();
+        nonces.confirm(nonces.reserve());
+        nonces.release(first);
+        nonces.fill_gaps(&provider).await;
+        // Both responses were used by the self-transfer.
+        assert!(provider.get_block_number().await.is_err());
+        assert_eq!(nonces.reserve(), U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn released_highest_nonce_waits_for_the_next_reservation() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U64::from(1)).unwrap();
+        let nonces = manager(0);
+        nonces.confirm(nonces.reserve());
+        let second = nonces.reserve();
+        nonces.release(second);
+        nonces.fill_gaps(&provider).await;
+        // Nothing was sent, so the queued response is still there.
+        assert!(provider.get_block_number().await.is_ok());
+        assert_eq!(nonces.reserve(), second);
+    }
+
+    #[tokio::test]
+    async fn rejected_gap_fill_keeps_the_nonce_released() {
+        let (provider, mock) = Provider::mocked();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "insufficient funds for gas".to_string(),
+            data: None,
+        }));
+        mock.push(U256::from(1)).unwrap(); // eth_gasPrice
+        let nonces = manager(0);
+        let first = nonces.reserve();
+        nonces.confirm(nonces.reserve());
+        nonces.release(first);
+        nonces.fill_gaps(&provider).await;
+        assert_eq!(nonces.reserve(), first);
+    }
+
+    #[test]
+    fn confirmed_nonce_is_not_released() {
+        let nonces = manager(3);
+        let nonce = nonces.reserve();
+        nonces.confirm(nonce);
+        nonces.release(nonce);
+        assert_eq!(nonces.reserve(), U256::from(4));
+    }
+
+    #[test]
+    fn classifies_node_errors() {
+        assert_eq!(SendError::classify("(code: -32000, message: nonce too low)"), SendError::NonceTooLow);
+        assert_eq!(SendError::classify("Nonce too low. Expected nonce to be 5"), SendError::NonceTooLow);
+        assert_eq!(
+            SendError::classify("replacement transaction underpriced"),
+            SendError::ReplacementUnderpriced
+        );
+        assert_eq!(SendError::classify("insufficient funds for gas"), SendError::Other);
+    }
+
+    #[tokio::test]
+    async fn sync_jumps_ahead_of_stale_local_nonce() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U256::from(12)).unwrap();
+        let nonces = manager(4);
+        assert_eq!(nonces.sync(&provider).await.unwrap(), U256::from(12));
+        assert_eq!(nonces.reserve(), U256::from(12));
+    }
+
+    #[tokio::test]
+    async fn sync_falls_back_to_refill_dropped_nonces() {
+        let (provider, mock) = Provider::mocked();
+        let nonces = manager(0);
+        for _ in 0..3 {
+            nonces.confirm(nonces.reserve());
+        }
+        // Nonces 1 and 2 were dropped from the mempool.
+        mock.push(U256::from(1)).unwrap();
+        assert_eq!(nonces.sync(&provider).await.unwrap(), U256::from(1));
+        assert_eq!(nonces.reserve(), U256::from(1));
+    }
+
+    #[tokio::test]
+    async fn sync_keeps_outstanding_reservations() {
+        let (provider, mock) = Provider::mocked();
+        let nonces = manager(0);
+        nonces.confirm(nonces.reserve());
+        let in_flight = nonces.reserve();
+        mock.push(U256::from(1)).unwrap();
+        assert_eq!(nonces.sync(&provider).await.unwrap(), in_flight + 1);
+        assert_eq!(nonces.reserve(), U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn sync_drops_released_nonces_mined_elsewhere() {
+        let (provider, mock) = Provider::mocked();
+        let nonces = manager(5);
+        let nonce = nonces.reserve();
+        nonces.release(nonce);
+        mock.push(U256::from(6)).unwrap();
+        nonces.sync(&provider).await.unwrap();
+        assert_eq!(nonces.reserve(), U256::from(6));
+    }
+
+    #[tokio::test]
+    async fn nonce_too_low_resyncs_immediately() {
+        let (provider, mock) = Provider::mocked();
+        let nonces = manager(2);
+        let nonce = nonces.reserve();
+        mock.push(U256::from(9)).unwrap();
+        let kind = nonces.handle_send_error(&provider, nonce, "nonce too low").await;
+        assert_eq!(kind, SendError::NonceTooLow);
+        assert_eq!(nonces.reserve(), U256::from(9));
+        mock.assert_request(
+            "eth_getTransactionCount",
+            (Address::repeat_byte(0x11), "pending"),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn other_errors_release_without_resync() {
+        let (provider, _mock) = Provider::mocked();
+        let nonces = manager(2);
+        let nonce = nonces.reserve();
+        let kind = nonces.handle_send_error(&provider, nonce, "insufficient funds").await;
+        assert_eq!(kind, SendError::Other);
+        assert_eq!(nonces.reserve(), nonce);
+    }
+}
@@ -0,0 +1,221 @@
+// src/position_book.rs
+//! Tracked positions plus two indexes ordered by liquidation price, so a
+//! price update finds the positions that crossed their threshold with one
+//! range query per side instead of a scan.
+use crate::risk::{ProtocolConstants, TrackedPosition};
+use ethers::types::U256;
+use std::collections::{BTreeSet, HashMap};
+
+type IndexKey = (U256, [u8; 32]);
+
+pub struct PositionBook {
+    constants: ProtocolConstants,
+    positions: HashMap<[u8; 32], TrackedPosition>,
+    // Liquidated once the price falls to the key or below.
+    longs: BTreeSet<IndexKey>,
+    // Liquidated once the price rises to the key or above.
+    shorts: BTreeSet<IndexKey>,
+}
+
+impl PositionBook {
+    pub fn new(constants: ProtocolConstants) -> Self {
+        Self {
+            constants,
+            positions: HashMap::new(),
+            longs: BTreeSet::new(),
+            shorts: BTreeSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn liquidation_reward(&self, position_id: &[u8; 32], price: U256) -> Option<U256> {
+        self.positions
+            .get(position_id)
+            .map(|position| position.liquidation_reward(price, &self.constants))
+    }
+
+    pub fn insert(&mut self, position_id: [u8; 32], position: TrackedPosition) {
+        self.unindex(&position_id);
+        self.index(position_id, &position);
+        self.positions.insert(position_id, position);
+    }
+
+    pub fn remove(&mut self, position_id: &[u8; 32]) -> Option<TrackedPosition> {
+        self.unindex(position_id);
+        self.positions.remove(position_id)
+    }
+
+    /// Applies a margin change and moves the position to its new
+    /// liquidation price. Returns the updated position, if tracked.
+    pub fn update_margin(
+        &mut self,
+        position_id: &[u8; 32],
+        update: impl FnOnce(U256) -> U256,
+    ) -> Option<&TrackedPosition> {
+        let mut position = self.remove(position_id)?;
+        position.margin = update(position.margin);
+        self.insert(*position_id, position);
+        self.positions.get(position_id)
+    }
+
+    /// Positions that are insolvent at `price`. Keys are conservative
+    /// bounds, so the range queries may include boundary positions the
+    /// contract still treats as solvent; each hit is re-checked with the
+    /// exact contract math.
+    pub fn insolvent_at(&self, price: U256) -> Vec<[u8; 32]> {
+        let longs = self.longs.range((price, [0u8; 32])..);
+        let shorts = self.shorts.range(..=(price, [0xff; 32]));
+        longs
+            .chain(shorts)
+            .map(|(_, position_id)| *position_id)
+            .filter(|position_id| {
+                self.positions
+                    .get(position_id)
+                    .is_some_and(|p| !p.is_solvent(price, &self.constants))
+            })
+            .collect()
+    }
+
+    fn index_key(&self, position_id: [u8; 32], position: &TrackedPosition) -> Option<IndexKey> {
+        // The bound, not the floored liquidation price: truncated profit can
+        // make a high-leverage position insolvent just past the latter.
+        match position.liquidation_bound(&self.constants) {
+            Some(price) => Some((price, position_id)),
+            // A long backed by more margin than its entry value still
+            // becomes insolvent at a zero price.
+            None if position.is_long => Some((U256::zero(), position_id)),
+            None => None,
+        }
+    }
+
+    fn index(&mut self, position_id: [u8; 32], position: &TrackedPosition) {
+        if let Some(key) = self.index_key(position_id, position) {
+            if position.is_long {
+                self.longs.insert(key);
+            } else {
+                self.shorts.insert(key);
+            }
+        }
+    }
+
+    fn unindex(&mut self, position_id: &[u8; 32]) {
+        let Some(position) = self.positions.get(position_id) else {
+            return;
+        };
+        if let Some(key) = self.index_key(*position_id, position) {
+            if position.is_long {
+                self.longs.remove(&key);
+            } else {
+                self.shorts.remove(&key);
+            }
+        }
+    }
+}
+
+impl Extend<([u8; 32], TrackedPosition)> for PositionBook {
+    fn extend<I: IntoIterator<Item = ([u8; 32], TrackedPosition)>>(&mut self, iter: I) {
+        for (position_id, position) in iter {
+            self.insert(position_id, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::risk::tests::{constants, position};
+    use std::time::{Duration, Instant};
+
+    fn id(n: u32) -> [u8; 32] {
+        let mut id = [0u8; 32];
+        id[28..].copy_from_slice(&n.to_be_bytes());
+        id
+    }
+
+    fn book() -> PositionBook {
+        let mut book = PositionBook::new(constants());
+        let mut n = 0;
+        for entry_price in [9_000, 10_000, 11_000] {
+            for margin in [500, 2_000, 10_000, 30_000] {
+                book.insert(id(n), position(true, entry_price, margin));
+                book.insert(id(n + 1), position(false, entry_price, margin));
+                n += 2;
+            }
+        }
+        // Past ~20x it is in profit at its threshold, where truncation bites.
+        book.insert(id(n), TrackedPosition { size: U256::from(101), ..position(true, 10_000, 9) });
+        book
+    }
+
+    fn sorted(mut ids: Vec<[u8; 32]>) -> Vec<[u8; 32]> {
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn range_queries_match_a_full_scan() {
+        let book = book();
+        for price in (7_000..14_000).step_by(7).chain([9_473, 9_474, 10_476, 10_477, 10_516, 10_517]) {
+            let price = U256::from(price);
+            let expected: Vec<_> = book
+                .positions
+                .iter()
+                .filter(|(_, p)| !p.is_solvent(price, &book.constants))
+                .map(|(id, _)| *id)
+                .collect();
+            assert_eq!(sorted(book.insolvent_at(price)), sorted(expected), "price {price}");
+        }
+    }
+
+    #[test]
+    fn boundary_positions_are_selected_only_once_insolvent() {
+        let mut book = PositionBook::new(constants());
+        book.insert(id(1), position(true, 10_000, 10_000));
+        book.insert(id(2), position(false, 10_000, 10_000));
+        assert!(book.insolvent_at(U256::from(9_474)).is_empty());
+        assert_eq!(book.insolvent_at(U256::from(9_473)), vec![id(1)]);
+        // The short's floored key is in range but the contract math keeps it.
+        assert!(book.insolvent_at(U256::from(10_476)).is_empty());
+        assert_eq!(book.insolvent_at(U256::from(10_477)), vec![id(2)]);
+    }
+
+    #[test]
+    fn margin_updates_and_removals_move_the_index() {
+        let mut book = PositionBook::new(constants());
+        book.insert(id(1), position(true, 10_000, 10_000));
+        let price = U256::from(9_473);
+        assert_eq!(book.insolvent_at(price), vec![id(1)]);
+
+        book.update_margin(&id(1), |margin| margin + U256::from(1_000));
+        assert!(book.insolvent_at(price).is_empty());
+        assert_eq!(book.insolvent_at(U256::from(9_368)), vec![id(1)]);
+
+        book.remove(&id(1));
+        assert!(book.insolvent_at(U256::zero()).is_empty());
+        assert_eq!(book.len(), 0);
+    }
+
+    // Wall-clock bound, only meaningful in release: `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn price_update_over_many_positions_is_sub_millisecond() {
+        let mut book = PositionBook::new(constants());
+        for n in 0..50_000 {
+            book.insert(id(n), position(n % 2 == 0, 10_000, 10_000 + u64::from(n)));
+        }
+        // Crosses a handful of the lowest-margin longs.
+        let price = book.positions[&id(20)].liquidation_bound(&book.constants).unwrap();
+        let fastest = (0..20)
+            .map(|_| {
+                let started = Instant::now();
+                assert!(!book.insolvent_at(price).is_empty());
+                started.elapsed()
+            })
+            .min()
+            .unwrap();
+        assert!(fastest < Duration::from_millis(1), "took {fastest:?}");
+    }
+}
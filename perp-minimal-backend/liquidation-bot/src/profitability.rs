@@ -0,0 +1,56 @@
+// src/profitability.rs
+//! Decides whether a confirmed liquidation is worth its gas. The reward is
+//! paid in collateral tokens, so gas cost is converted with the configured
+//! collateral-per-native-token rate before the two are compared.
+use anyhow::Result;
+use ethers::prelude::*;
+use std::collections::HashSet;
+
+const WEI_PER_NATIVE: u64 = 1_000_000_000_000_000_000;
+
+pub struct ProfitPolicy {
+    /// Collateral base units worth one native token; `None` disables gating.
+    pub collateral_per_native: Option<U256>,
+    /// Minimum reward minus gas cost, in collateral base units.
+    pub min_profit: U256,
+    /// Positions liquidated regardless of cost, for protocol health.
+    pub force_liquidate: HashSet<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Liquidate,
+    Forced,
+    /// Not worth it at current fees; reconsidered on the next price update.
+    Defer { reward: U256, cost: U256 },
+}
+
+impl ProfitPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.collateral_per_native.is_some()
+    }
+
+    pub fn decide(&self, position_id: &[u8; 32], reward: U256, gas: U256, gas_price: U256) -> Decision {
+        if self.force_liquidate.contains(position_id) {
+            return Decision::Forced;
+        }
+        let Some(collateral_per_native) = self.collateral_per_native else {
+            return Decision::Liquidate;
+        };
+        let cost = gas * gas_price * collateral_per_native / U256::from(WEI_PER_NATIVE);
+        if reward > cost && reward - cost >= self.min_profit {
+            Decision::Liquidate
+        } else {
+            Decision::Defer { reward, cost }
+        }
+    }
+}
+
+/// Parses a comma-separated list of 0x-prefixed position IDs.
+pub fn parse_position_ids(list: &str) -> Result<HashSet<[u8; 32]>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| Ok(id.parse::<H256>()?.0))
+        .collect()
+}
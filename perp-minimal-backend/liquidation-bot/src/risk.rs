@@ -0,0 +1,145 @@
+// src/risk.rs
+//! Tracked positions and the protocol constants, checked locally on every
+//! price update with the contract math from `keeper_tx::risk`. The chain is
+//! only asked to confirm candidates.
+use ethers::types::{Address, U256};
+use keeper_tx::risk::{MarginParams, PositionTerms};
+use serde::{Deserialize, Serialize};
+
+/// Read once at startup; the contract defines them as constants.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolConstants {
+    pub price_precision: U256,
+    pub bps_divisor: U256,
+    pub maintenance_margin_ratio_bps: U256,
+    pub liquidation_fee_bps: U256,
+}
+
+impl ProtocolConstants {
+    fn margin_params(&self) -> MarginParams {
+        MarginParams {
+            price_precision: self.price_precision,
+            bps_divisor: self.bps_divisor,
+            maintenance_margin_ratio_bps: self.maintenance_margin_ratio_bps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedPosition {
+    pub owner: Address,
+    pub size: U256,
+    /// Margin after the opening fee, adjusted by MarginAdded/MarginRemoved.
+    pub margin: U256,
+    pub entry_price: U256,
+    pub is_long: bool,
+}
+
+impl TrackedPosition {
+    fn terms(&self) -> PositionTerms {
+        PositionTerms {
+            is_long: self.is_long,
+            size: self.size,
+            margin: self.margin,
+            entry_price: self.entry_price,
+        }
+    }
+
+    pub fn is_solvent(&self, price: U256, constants: &ProtocolConstants) -> bool {
+        self.terms().is_solvent(price, &constants.margin_params())
+    }
+
+    /// Fee minted to the liquidator by `liquidate`, capped at the margin.
+    pub fn liquidation_reward(&self, price: U256, constants: &ProtocolConstants) -> U256 {
+        let position_value = self.size * price / constants.price_precision;
+        (position_value * constants.liquidation_fee_bps / constants.bps_divisor).min(self.margin)
+    }
+
+    /// See `PositionTerms::liquidation_bound`.
+    pub fn liquidation_bound(&self, constants: &ProtocolConstants) -> Option<U256> {
+        self.terms().liquidation_bound(&constants.margin_params())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn constants() -> ProtocolConstants {
+        ProtocolConstants {
+            price_precision: U256::from(100),
+            bps_divisor: U256::from(10_000),
+            maintenance_margin_ratio_bps: U256::from(500),
+            liquidation_fee_bps: U256::from(100),
+        }
+    }
+
+    fn liquidation_price(position: &TrackedPosition, constants: &ProtocolConstants) -> Option<U256> {
+        position.terms().liquidation_price(&constants.margin_params())
+    }
+
+    pub(crate) fn position(is_long: bool, entry_price: u64, margin: u64) -> TrackedPosition {
+        TrackedPosition {
+            owner: Address::repeat_byte(0x22),
+            size: U256::from(1_000),
+            margin: U256::from(margin),
+            entry_price: U256::from(entry_price),
+            is_long,
+        }
+    }
+
+    #[test]
+    fn long_is_insolvent_at_its_liquidation_price() {
+        let constants = constants();
+        let long = position(true, 10_000, 10_000);
+        // (10_000 * 1_000 - 10_000 * 100) * 10_000 / (1_000 * 9_500) = 9_473.68
+        assert_eq!(liquidation_price(&long, &constants), Some(U256::from(9_473)));
+        assert!(!long.is_solvent(U256::from(9_473), &constants));
+        assert!(long.is_solvent(U256::from(9_474), &constants));
+    }
+
+    #[test]
+    fn short_at_its_floored_liquidation_price_can_still_be_solvent() {
+        let constants = constants();
+        let short = position(false, 10_000, 10_000);
+        // (10_000 * 1_000 + 10_000 * 100) * 10_000 / (1_000 * 10_500) = 10_476.19
+        assert_eq!(liquidation_price(&short, &constants), Some(U256::from(10_476)));
+        assert!(short.is_solvent(U256::from(10_476), &constants));
+        assert!(!short.is_solvent(U256::from(10_477), &constants));
+    }
+
+    #[test]
+    fn liquidation_price_bounds_solvency_across_positions() {
+        let constants = constants();
+        for entry_price in [9_999, 10_000, 10_001, 12_345] {
+            for margin in [1, 999, 5_000, 10_001, 33_333] {
+                let long = position(true, entry_price, margin);
+                let price = liquidation_price(&long, &constants).unwrap();
+                assert!(long.is_solvent(price + 1, &constants), "long {entry_price}/{margin}");
+
+                let short = position(false, entry_price, margin);
+                let price = liquidation_price(&short, &constants).unwrap();
+                assert!(short.is_solvent(price - 1, &constants), "short {entry_price}/{margin}");
+                assert!(!short.is_solvent(price + 1, &constants), "short {entry_price}/{margin}");
+            }
+        }
+    }
+
+    #[test]
+    fn no_liquidation_price_without_a_positive_threshold() {
+        let constants = constants();
+        // Margin covers the whole entry value.
+        assert_eq!(liquidation_price(&position(true, 10_000, 100_000), &constants), None);
+        let empty = TrackedPosition { size: U256::zero(), ..position(false, 10_000, 10_000) };
+        assert_eq!(liquidation_price(&empty, &constants), None);
+    }
+
+    #[test]
+    fn liquidation_reward_is_capped_at_margin() {
+        let constants = constants();
+        let price = U256::from(9_473);
+        // 1% of a 94_730 notional.
+        assert_eq!(position(true, 10_000, 10_000).liquidation_reward(price, &constants), U256::from(947));
+        assert_eq!(position(true, 10_000, 500).liquidation_reward(price, &constants), U256::from(500));
+    }
+}
@@ -0,0 +1,143 @@
+// src/store.rs
+//! Embedded sled store that lets the bot resume after a restart without
+//! replaying the whole chain or forgetting liquidations still in flight.
+use crate::risk::TrackedPosition;
+use anyhow::Result;
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+use std::collections::HashMap;
+
+const CURSOR_KEY: &str = "cursor";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptStatus {
+    Pending,
+    Confirmed,
+    Reverted,
+    /// The node rejected the transaction or it disappeared from the mempool.
+    Failed,
+    /// Never sent: the pre-flight simulation against the pending block or
+    /// the gas estimate reverted.
+    Skipped,
+    /// Never sent: not worth its gas, or fees could not be read to tell.
+    /// Reconsidered on the next price update.
+    Deferred,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationAttempt {
+    /// Latest broadcast; earlier ones replaced at higher fees are kept in
+    /// `replaced_tx_hashes`, since any of them may still be mined.
+    pub tx_hash: Option<H256>,
+    #[serde(default)]
+    pub replaced_tx_hashes: Vec<H256>,
+    pub nonce: Option<u64>,
+    pub status: AttemptStatus,
+    pub submitted_at: u64,
+    pub block_number: Option<u64>,
+    pub error: Option<String>,
+    /// Wallet that sent the broadcasts, credited when the attempt settles.
+    #[serde(default)]
+    pub sender: Option<Address>,
+}
+
+#[derive(Clone)]
+pub struct Store {
+    _db: Db,
+    // K: position_id (bytes32), V: TrackedPosition (json)
+    pub positions: Tree,
+    // K: position_id (bytes32), V: latest LiquidationAttempt (json)
+    pub liquidations: Tree,
+    // K: "cursor", V: block (u64 BE) ++ log_index (u64 BE)
+    pub bot_state: Tree,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            positions: db.open_tree("positions")?,
+            liquidations: db.open_tree("liquidations")?,
+            bot_state: db.open_tree("bot_state")?,
+            _db: db,
+        })
+    }
+
+    pub fn load_positions(&self) -> Result<HashMap<[u8; 32], TrackedPosition>> {
+        self.positions
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let position_id: [u8; 32] = key.as_ref().try_into()?;
+                Ok((position_id, serde_json::from_slice(&value)?))
+            })
+            .collect()
+    }
+
+    pub fn insert_position(&self, position_id: [u8; 32], position: &TrackedPosition) -> Result<()> {
+        self.positions
+            .insert(position_id, serde_json::to_vec(position)?)?;
+        Ok(())
+    }
+
+    pub fn remove_position(&self, position_id: &[u8; 32]) -> Result<()> {
+        self.positions.remove(position_id)?;
+        Ok(())
+    }
+
+    /// (block, log_index) of the last applied position event. Every event at
+    /// or before it is reflected in `positions`; `(block, u64::MAX)` marks a
+    /// fully scanned block.
+    pub fn cursor(&self) -> Result<Option<(u64, u64)>> {
+        Ok(self.bot_state.get(CURSOR_KEY)?.and_then(|v| decode_cursor(&v)))
+    }
+
+    /// Moves the cursor forward; an older position is ignored.
+    pub fn advance_cursor(&self, position: (u64, u64)) -> Result<()> {
+        self.bot_state.fetch_and_update(CURSOR_KEY, |current| {
+            let next = current
+                .and_then(decode_cursor)
+                .map_or(position, |c| c.max(position));
+            let mut bytes = next.0.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&next.1.to_be_bytes());
+            Some(bytes)
+        })?;
+        Ok(())
+    }
+
+    pub fn get_attempt(&self, position_id: &[u8; 32]) -> Result<Option<LiquidationAttempt>> {
+        Ok(match self.liquidations.get(position_id)? {
+            Some(data) => Some(serde_json::from_slice(&data)?),
+            None => None,
+        })
+    }
+
+    /// Left to sled's periodic flush. An attempt lost in a crash only means
+    /// the position is simulated again after restart, which skips it once
+    /// the broadcast is mined.
+    pub fn record_attempt(&self, position_id: [u8; 32], attempt: &LiquidationAttempt) -> Result<()> {
+        self.liquidations
+            .insert(position_id, serde_json::to_vec(attempt)?)?;
+        Ok(())
+    }
+
+    pub fn pending_attempts(&self) -> Result<Vec<([u8; 32], LiquidationAttempt)>> {
+        let mut pending = Vec::new();
+        for entry in self.liquidations.iter() {
+            let (key, value) = entry?;
+            let attempt: LiquidationAttempt = serde_json::from_slice(&value)?;
+            if attempt.status == AttemptStatus::Pending {
+                pending.push((key.as_ref().try_into()?, attempt));
+            }
+        }
+        Ok(pending)
+    }
+}
+
+fn decode_cursor(bytes: &[u8]) -> Option<(u64, u64)> {
+    let block = u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?);
+    let log_index = u64::from_be_bytes(bytes.get(8..16)?.try_into().ok()?);
+    Some((block, log_index))
+}
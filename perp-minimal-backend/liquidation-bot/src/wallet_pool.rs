@@ -0,0 +1,198 @@
+// src/wallet_pool.rs
+//! A pool of liquidator wallets, each with its own nonce sequence, so
+//! liquidations in a crash are not serialized behind a single account. A
+//! liquidation goes to the funded wallet with the fewest transactions in
+//! flight; wallets whose native balance falls below the floor are skipped.
+use crate::{nonce_manager::NonceManager, store::AttemptStatus, ClearingHouseV2};
+use anyhow::{anyhow, Result};
+use ethers::{prelude::*, utils::format_ether};
+use std::{
+    ops::Deref,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+#[derive(Debug, Default, Clone)]
+pub struct WalletStats {
+    pub sent: u64,
+    pub confirmed: u64,
+    pub reverted: u64,
+    pub failed: u64,
+    /// Wei paid for every mined broadcast, cancellations included.
+    pub gas_spent: U256,
+}
+
+#[derive(Debug, Default)]
+struct WalletState {
+    balance: U256,
+    in_flight: usize,
+    stats: WalletStats,
+}
+
+pub struct Wallet {
+    pub clearing_house: ClearingHouseV2<Client>,
+    pub nonce_manager: NonceManager,
+    state: Mutex<WalletState>,
+}
+
+impl Wallet {
+    pub fn address(&self) -> Address {
+        self.clearing_house.client().address()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WalletState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn balance(&self) -> U256 {
+        self.lock().balance
+    }
+
+    pub async fn refresh_balance(&self) -> Result<U256> {
+        let balance = self
+            .clearing_house
+            .client()
+            .get_balance(self.address(), None)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        self.lock().balance = balance;
+        Ok(balance)
+    }
+
+    /// Counts a broadcast that reached the node.
+    pub fn record_sent(&self) {
+        self.lock().stats.sent += 1;
+    }
+
+    /// Counts a finished liquidation and the gas its mined broadcast paid.
+    pub fn record_outcome(&self, status: AttemptStatus, receipt: Option<&TransactionReceipt>) {
+        let mut state = self.lock();
+        match status {
+            AttemptStatus::Confirmed => state.stats.confirmed += 1,
+            AttemptStatus::Reverted => state.stats.reverted += 1,
+            AttemptStatus::Failed => state.stats.failed += 1,
+            AttemptStatus::Pending | AttemptStatus::Skipped | AttemptStatus::Deferred => {}
+        }
+        if let Some(receipt) = receipt {
+            let gas_used = receipt.gas_used.unwrap_or_default();
+            let gas_price = receipt.effective_gas_price.unwrap_or_default();
+            state.stats.gas_spent += gas_used * gas_price;
+        }
+    }
+}
+
+/// A wallet assigned to one liquidation; frees its slot when dropped.
+pub struct Assignment(Arc<Wallet>);
+
+impl Deref for Assignment {
+    type Target = Wallet;
+
+    fn deref(&self) -> &Wallet {
+        &self.0
+    }
+}
+
+impl Drop for Assignment {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+}
+
+pub struct WalletPool {
+    wallets: Vec<Arc<Wallet>>,
+    /// Native balance below which a wallet gets no new liquidations.
+    pub min_balance: U256,
+    assign_lock: Mutex<()>,
+}
+
+impl WalletPool {
+    /// Connects one wallet per comma-separated private key. Each starts at
+    /// its pending nonce, so transactions sent before a restart are kept.
+    pub async fn connect(
+        provider: &Provider<Http>,
+        chain_id: u64,
+        clearing_house_address: Address,
+        private_keys: &str,
+        min_balance: U256,
+    ) -> Result<Self> {
+        let mut wallets: Vec<Arc<Wallet>> = Vec::new();
+        for key in private_keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+            let signer = LocalWallet::from_str(key)?.with_chain_id(chain_id);
+            let address = signer.address();
+            if wallets.iter().any(|wallet| wallet.address() == address) {
+                continue;
+            }
+            let client = Arc::new(SignerMiddleware::new(provider.clone(), signer));
+            let nonce = client.get_transaction_count(address, Some(BlockNumber::Pending.into())).await?;
+            let wallet = Wallet {
+                clearing_house: ClearingHouseV2::new(clearing_house_address, client),
+                nonce_manager: NonceManager::new(address, nonce),
+                state: Mutex::default(),
+            };
+            wallet.refresh_balance().await?;
+            wallets.push(Arc::new(wallet));
+        }
+        if wallets.is_empty() {
+            return Err(anyhow!("no liquidator private keys configured"));
+        }
+        Ok(Self { wallets, min_balance, assign_lock: Mutex::new(()) })
+    }
+
+    /// The first configured wallet, used for reads and event streams.
+    pub fn primary(&self) -> &Arc<Wallet> {
+        &self.wallets[0]
+    }
+
+    pub fn wallets(&self) -> &[Arc<Wallet>] {
+        &self.wallets
+    }
+
+    pub fn get(&self, address: Address) -> Option<&Arc<Wallet>> {
+        self.wallets.iter().find(|wallet| wallet.address() == address)
+    }
+
+    /// Picks the wallet above the balance floor with the fewest
+    /// liquidations in flight, preferring the larger balance on a tie.
+    /// `None` if every wallet is below the floor.
+    pub fn assign(&self) -> Option<Assignment> {
+        let _guard = self.assign_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let wallet = self
+            .wallets
+            .iter()
+            .filter(|wallet| wallet.balance() >= self.min_balance)
+            .min_by_key(|wallet| {
+                let state = wallet.lock();
+                (state.in_flight, std::cmp::Reverse(state.balance))
+            })?;
+        wallet.lock().in_flight += 1;
+        Some(Assignment(Arc::clone(wallet)))
+    }
+
+    /// Prints one line per wallet with its balance and liquidation counts.
+    pub fn report(&self) {
+        println!("📊 Liquidator wallets:");
+        for wallet in &self.wallets {
+            let (balance, in_flight, stats) = {
+                let state = wallet.lock();
+                (state.balance, state.in_flight, state.stats.clone())
+            };
+            let floor = if balance < self.min_balance { " ⚠️ below floor" } else { "" };
+            println!(
+                "   {:#x}: balance {} ETH{}, nonce {}, in flight {}, sent {}, confirmed {}, reverted {}, failed {}, gas spent {} ETH",
+                wallet.address(),
+                format_ether(balance),
+                floor,
+                wallet.nonce_manager.next(),
+                in_flight,
+                stats.sent,
+                stats.confirmed,
+                stats.reverted,
+                stats.failed,
+                format_ether(stats.gas_spent)
+            );
+        }
+    }
+}
@@ -9,6 +9,11 @@ use serde::Deserialize;
 use std::{env, str::FromStr, sync::Arc, time::Duration};
 use anyhow::Result;
 
+use keeper_tx::{
+    fees::{FeePolicy, Urgency},
+    tx_tracker::{self, TxOutcome},
+};
+
 // Generate the an `Oracle` struct with all the type-safe bindings from the ABI.
 // This is a build-time macro that reads the ABI file.
 abigen!(Oracle, "abi/Oracle.json");
@@ -55,6 +60,7 @@ async fn main() -> Result<()> {
     let price_threshold: f64 = env::var("PRICE_CHANGE_THRESHOLD")
         .expect("PRICE_CHANGE_THRESHOLD must be set")
         .parse()?;
+    let fee_policy = FeePolicy::from_env()?;
 
     // Set up the Ethereum provider and client.
     // Using a WebSocket provider is best for long-running applications.
@@ -105,6 +111,7 @@ async fn main() -> Result<()> {
         let new_price_u256 = to_u256_price(current_price_f64);
 
         // 2. Caching and Threshold Logic
+        let mut urgency = fee_policy.default_urgency;
         if let Some(last_price) = last_sent_price {
             let last_f64 = last_price.as_u128() as f64 / 1e18;
             let change = ((current_price_f64 - last_f64) / last_f64).abs();
@@ -116,6 +123,10 @@ async fn main() -> Result<()> {
                 continue;
             }
             println!("Price change of {:.4}% detected. Submitting update...", change * 100.0);
+            // Large moves make liquidations depend on this update landing quickly.
+            if change >= price_threshold * 2.0 {
+                urgency = Urgency::High;
+            }
         } else {
             println!("No last price cached. Submitting first price update...");
         }
@@ -123,18 +134,33 @@ async fn main() -> Result<()> {
         // 3. Send Transaction to the Smart Contract
         println!("Submitting price {:.18} to the contract...", new_price_u256);
 
-        let call = oracle_contract.set_price(new_price_u256);
-        match call.send().await {
-            Ok(pending_tx) => {
-                println!("Transaction sent. Waiting for confirmation...");
-                match pending_tx.await {
-                    Ok(Some(receipt)) => {
+        // Fill nonce and gas up front; the tracker sets fees and replaces the
+        // transaction at higher fees if it gets stuck.
+        let mut tx = oracle_contract.set_price(new_price_u256).tx;
+        match client.fill_transaction(&mut tx, None).await {
+            Ok(()) => {
+                let outcome = tx_tracker::send_tracked(
+                    client.as_ref(),
+                    tx,
+                    &fee_policy,
+                    urgency,
+                    // A price update is never obsolete, so it is never cancelled.
+                    || async { false },
+                    |tx_hash| println!("Transaction {:#x} sent. Waiting for confirmation...", tx_hash),
+                )
+                .await;
+                match outcome {
+                    Ok(TxOutcome::Mined(receipt)) => {
                         println!("✅ Transaction confirmed! Hash: {:#x}", receipt.transaction_hash);
                         // Update our cache with the new price
                         last_sent_price = Some(new_price_u256);
                     }
-                    Ok(None) => {
-                        eprintln!("[ERROR] Transaction dropped from mempool.");
+                    // Never asked for, but either way the price did not land.
+                    Ok(TxOutcome::Cancelled(receipt)) => {
+                        eprintln!("[ERROR] Transaction nonce was taken by a cancellation: {:#x}", receipt.transaction_hash);
+                    }
+                    Ok(TxOutcome::NonceConsumed) => {
+                        eprintln!("[ERROR] Transaction nonce was used by another transaction.");
                     }
                     Err(e) => {
                         eprintln!("[ERROR] Failed to confirm transaction: {}", e);